@@ -0,0 +1,28 @@
+// Running mean of `inputTex`, used by `ProgressiveAccumulation`.
+// `prevTex` holds the mean of the first `accum_sample_count` samples.
+
+uniform texture2D inputTex;
+uniform texture2D prevTex;
+uniform restrict writeonly image2D outputTex;
+
+layout(std140) uniform globals {
+    vec4 outputTex_size;
+    uint accum_sample_count;
+};
+
+layout (local_size_x = 8, local_size_y = 8) in;
+void main() {
+    ivec2 pix = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pix, ivec2(outputTex_size.xy)))) {
+        return;
+    }
+
+    vec4 result = texelFetch(inputTex, pix, 0);
+
+    if (accum_sample_count > 0) {
+        vec4 prev = texelFetch(prevTex, pix, 0);
+        result = mix(prev, result, 1.0 / float(accum_sample_count + 1));
+    }
+
+    imageStore(outputTex, pix, result);
+}
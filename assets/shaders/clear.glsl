@@ -0,0 +1,15 @@
+uniform restrict writeonly image2D outputTex;
+
+layout(std140) uniform globals {
+    vec4 outputTex_size;
+};
+
+layout (local_size_x = 8, local_size_y = 8) in;
+void main() {
+    ivec2 pix = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pix, ivec2(outputTex_size.xy)))) {
+        return;
+    }
+
+    imageStore(outputTex, pix, vec4(0.0));
+}
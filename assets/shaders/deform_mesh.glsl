@@ -0,0 +1,108 @@
+// Applies morph targets and linear blend skinning to `rest_vertex_buf`.
+// The output uses the same layout, and can be bound as `mesh_vertex_buf`.
+
+struct VertexPacked {
+    float x, y, z;
+    uint normal;
+};
+
+layout(std430) buffer rest_vertex_buf {
+    VertexPacked rest_vertices[];
+};
+
+layout(std430) buffer skin_joint_buf {
+    uvec4 skin_joints[];
+};
+
+layout(std430) buffer skin_weight_buf {
+    vec4 skin_weights[];
+};
+
+// Position delta followed by normal delta, per morph target, per vertex
+layout(std430) buffer morph_delta_buf {
+    float morph_deltas[];
+};
+
+layout(std430) buffer morph_weight_buf {
+    float morph_weights[];
+};
+
+// Followed by the world transform of the mesh node, for vertices without skin weights
+layout(std430) buffer joint_matrix_buf {
+    mat4 joint_matrices[];
+};
+
+layout(std430) buffer outputBuffer {
+    VertexPacked out_vertices[];
+};
+
+layout(std140) uniform globals {
+    uint vertex_count;
+    uint morph_target_count;
+    uint skinned;
+};
+
+// Must match `pack_unit_direction_11_10_11` in mesh.rs
+vec3 unpack_unit_direction_11_10_11(uint pck) {
+    return vec3(
+        float(pck & ((1u << 11u) - 1u)) * (2.0 / float((1u << 11u) - 1u)) - 1.0,
+        float((pck >> 11u) & ((1u << 10u) - 1u)) * (2.0 / float((1u << 10u) - 1u)) - 1.0,
+        float(pck >> 21u) * (2.0 / float((1u << 11u) - 1u)) - 1.0
+    );
+}
+
+uint pack_unit_direction_11_10_11(vec3 n) {
+    uint x = uint((clamp(n.x, -1.0, 1.0) * 0.5 + 0.5) * float((1u << 11u) - 1u));
+    uint y = uint((clamp(n.y, -1.0, 1.0) * 0.5 + 0.5) * float((1u << 10u) - 1u));
+    uint z = uint((clamp(n.z, -1.0, 1.0) * 0.5 + 0.5) * float((1u << 11u) - 1u));
+    return (z << 21u) | (y << 11u) | x;
+}
+
+layout (local_size_x = 64, local_size_y = 1) in;
+void main() {
+    uint vertex_idx = gl_GlobalInvocationID.x;
+    if (vertex_idx >= vertex_count) {
+        return;
+    }
+
+    VertexPacked v = rest_vertices[vertex_idx];
+    vec3 pos = vec3(v.x, v.y, v.z);
+    vec3 normal = unpack_unit_direction_11_10_11(v.normal);
+
+    for (uint target_idx = 0; target_idx < morph_target_count; ++target_idx) {
+        float w = morph_weights[target_idx];
+        uint o = (target_idx * vertex_count + vertex_idx) * 6;
+        pos += w * vec3(morph_deltas[o + 0], morph_deltas[o + 1], morph_deltas[o + 2]);
+        normal += w * vec3(morph_deltas[o + 3], morph_deltas[o + 4], morph_deltas[o + 5]);
+    }
+
+    if (skinned != 0) {
+        uvec4 joints = skin_joints[vertex_idx];
+        vec4 weights = skin_weights[vertex_idx];
+        float weight_sum = dot(weights, vec4(1.0));
+
+        // Skinned output is in world space. Vertices without weights, e.g. of primitives
+        // without `JOINTS_0` in a skinned mesh, only get the transform of the mesh node.
+        mat4 skin_xform;
+        if (weight_sum > 0.0) {
+            skin_xform = (
+                weights.x * joint_matrices[joints.x] +
+                weights.y * joint_matrices[joints.y] +
+                weights.z * joint_matrices[joints.z] +
+                weights.w * joint_matrices[joints.w]
+            ) / weight_sum;
+        } else {
+            skin_xform = joint_matrices[joint_matrices.length() - 1];
+        }
+
+        pos = (skin_xform * vec4(pos, 1.0)).xyz;
+
+        // Cofactor matrix; handles non-uniform scaling and mirroring
+        mat3 m = mat3(skin_xform);
+        mat3 cof = mat3(cross(m[1], m[2]), cross(m[2], m[0]), cross(m[0], m[1]));
+        normal = cof * normal * sign(determinant(m));
+    }
+
+    normal = normalize(normal);
+    out_vertices[vertex_idx] = VertexPacked(pos.x, pos.y, pos.z, pack_unit_direction_11_10_11(normal));
+}
@@ -0,0 +1,233 @@
+use crate::backend::texture::{create_texture, Texture, TextureKey};
+use crate::shader::{
+    dispatch_compute_tex, resolve, ResolvedShaderUniformHolder, ResolvedShaderUniformValue,
+};
+use crate::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// The accumulated image is carried over between frames by `history_tex`.
+// The number of samples in it is tracked here, keyed by the accumulation id.
+struct AccumulationState {
+    key: TextureKey,
+    sample_count: u32,
+    reset_token: u64,
+}
+
+lazy_static! {
+    static ref ACCUMULATION_STATES: Mutex<HashMap<u64, AccumulationState>> =
+        { Mutex::new(HashMap::new()) };
+}
+
+static NEXT_ACCUMULATION_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_RESET_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+fn accumulation_history_name(accumulation_id: u64) -> String {
+    format!("accumulation {}", accumulation_id)
+}
+
+// Returns a new value whenever any asset in `deps` is invalidated, as that's
+// the only time this gets evaluated again.
+#[snoozy]
+pub async fn accumulation_reset_token_snoozy(
+    ctx: Context,
+    deps: &ShaderUniformBundle,
+) -> Result<u64> {
+    resolve(ctx, deps.clone()).await?;
+    Ok(NEXT_RESET_TOKEN.fetch_add(1, Ordering::Relaxed))
+}
+
+// Adds `input` to the running mean in `prev`, which is the output of the previous frame,
+// and returns the new mean. `accumulation_id` identifies the sample count to continue from.
+#[snoozy]
+pub async fn accumulate_tex_snoozy(
+    mut ctx: Context,
+    key: &TextureKey,
+    accumulation_id: &u64,
+    input: &SnoozyRef<Texture>,
+    prev: &SnoozyRef<Texture>,
+    reset_token: &SnoozyRef<u64>,
+    reset: &bool,
+) -> Result<Texture> {
+    let input = (*ctx.get(input).await?).clone();
+    let prev = (*ctx.get(prev).await?).clone();
+    let reset_token = *ctx.get(reset_token).await?;
+    let cs = ctx
+        .get(&load_cs(crate::asset!("shaders/accumulate.glsl")))
+        .await?;
+    ctx.set_debug_name(&cs.name);
+
+    let prev_sample_count = {
+        let mut states = ACCUMULATION_STATES.lock().unwrap();
+        let state = states
+            .entry(*accumulation_id)
+            .or_insert_with(|| AccumulationState {
+                key: *key,
+                sample_count: 0,
+                reset_token,
+            });
+
+        // A new size or format also means new history
+        if *reset || state.reset_token != reset_token || state.key != *key {
+            state.key = *key;
+            state.reset_token = reset_token;
+            state.sample_count = 0;
+        }
+
+        let prev_sample_count = state.sample_count;
+        state.sample_count += 1;
+        prev_sample_count
+    };
+
+    // Nothing to blend with after a reset
+    let prev = if prev_sample_count > 0 {
+        prev
+    } else {
+        input.clone()
+    };
+
+    let output = create_texture(*key);
+    dispatch_compute_tex(
+        &cs,
+        &output,
+        vec![
+            ResolvedShaderUniformHolder::new(
+                "inputTex",
+                ResolvedShaderUniformValue::Texture(input),
+            ),
+            ResolvedShaderUniformHolder::new("prevTex", ResolvedShaderUniformValue::Texture(prev)),
+            ResolvedShaderUniformHolder::new(
+                "accum_sample_count",
+                ResolvedShaderUniformValue::Uint32(prev_sample_count),
+            ),
+        ],
+    )?;
+
+    Ok(output)
+}
+
+// Keeps a running mean of a noisy input, such as a path tracer, while the camera is still.
+// Accumulation restarts when the camera moves, or when any asset in `reset_deps`
+// is invalidated. With a target sample count, rendering idles once it's reached:
+//
+//   let mut accum = ProgressiveAccumulation::new(
+//       TextureKey::new(width, height, vk::Format::R32G32B32A32_SFLOAT),
+//       shader_uniforms!(scene: scene.clone()),
+//   )
+//   .with_target_sample_count(1024);
+//
+//   // Every frame, after updating the `CameraConvergenceEnforcer`:
+//   if accum.begin_frame(camera.is_converged()) {
+//       accum.accumulate(compute_tex(
+//           tex_key,
+//           load_cs(asset!("shaders/path_trace.glsl")),
+//           shader_uniforms!(: accum.sample_uniforms(), scene: scene.clone()),
+//       ));
+//   }
+//
+// The texture key should use a float format, as the mean is stored at its precision.
+pub struct ProgressiveAccumulation {
+    id: u64,
+    tex_key: TextureKey,
+    reset_token: SnoozyRef<u64>,
+    target_sample_count: Option<u32>,
+    output: Option<SnoozyRef<Texture>>,
+    needs_reset: bool,
+    is_idle: bool,
+}
+
+impl ProgressiveAccumulation {
+    pub fn new(tex_key: TextureKey, reset_deps: ShaderUniformBundle) -> Self {
+        Self {
+            id: NEXT_ACCUMULATION_ID.fetch_add(1, Ordering::Relaxed),
+            tex_key,
+            reset_token: accumulation_reset_token(reset_deps),
+            target_sample_count: None,
+            output: None,
+            needs_reset: true,
+            is_idle: false,
+        }
+    }
+
+    pub fn with_target_sample_count(mut self, target_sample_count: u32) -> Self {
+        self.target_sample_count = Some(target_sample_count);
+        self
+    }
+
+    // Returns whether a new sample should be rendered and passed to `accumulate` this frame.
+    // This is false once the target sample count has been reached with a converged camera.
+    pub fn begin_frame(&mut self, camera_converged: bool) -> bool {
+        if !camera_converged {
+            self.needs_reset = true;
+        }
+
+        // Invalidated `reset_deps` re-evaluate the last output, which restarts the count
+        self.is_idle = !self.needs_reset
+            && self.output.is_some()
+            && self
+                .target_sample_count
+                .map_or(false, |target| self.sample_count() >= target);
+
+        !self.is_idle
+    }
+
+    // `accum_sample_count`: the index of the sample being rendered, e.g. to seed random numbers
+    pub fn sample_uniforms(&self) -> ShaderUniformBundle {
+        let sample_idx = if self.needs_reset {
+            0
+        } else {
+            self.sample_count()
+        };
+
+        shader_uniforms!(accum_sample_count: sample_idx)
+    }
+
+    pub fn accumulate(&mut self, input: SnoozyRef<Texture>) -> SnoozyRef<Texture> {
+        let history_name = accumulation_history_name(self.id);
+        let output = store_history_tex(
+            &history_name,
+            accumulate_tex(
+                self.tex_key,
+                self.id,
+                input,
+                history_tex(&history_name, self.tex_key),
+                self.reset_token.clone(),
+                self.needs_reset,
+            ),
+        );
+
+        self.needs_reset = false;
+        self.output = Some(output.clone());
+        output
+    }
+
+    // The mean of all samples so far
+    pub fn output(&self) -> Option<SnoozyRef<Texture>> {
+        self.output.clone()
+    }
+
+    // Samples in the mean, as of the last evaluation of `output`
+    pub fn sample_count(&self) -> u32 {
+        ACCUMULATION_STATES
+            .lock()
+            .unwrap()
+            .get(&self.id)
+            .map_or(0, |state| state.sample_count)
+    }
+
+    pub fn target_sample_count(&self) -> Option<u32> {
+        self.target_sample_count
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.is_idle
+    }
+}
+
+impl Drop for ProgressiveAccumulation {
+    fn drop(&mut self) {
+        ACCUMULATION_STATES.lock().unwrap().remove(&self.id);
+        crate::history::forget_history_tex(&accumulation_history_name(self.id));
+    }
+}
@@ -0,0 +1,326 @@
+use super::*;
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Abomonation)]
+pub enum GltfAnimationInterpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Abomonation)]
+pub enum GltfAnimationProperty {
+    Translation,
+    Rotation,
+    Scale,
+    MorphWeights,
+}
+
+#[derive(Clone, Abomonation)]
+pub struct GltfAnimationChannel {
+    pub node: u32,
+    pub property: GltfAnimationProperty,
+    pub interpolation: GltfAnimationInterpolation,
+    // Keyframe times in seconds
+    pub times: Vec<f32>,
+    // Flattened keyframe values. With cubic spline interpolation, every keyframe
+    // stores an in-tangent, a value and an out-tangent, in that order.
+    pub values: Vec<f32>,
+}
+
+impl GltfAnimationChannel {
+    // Number of scalars in a single value of the animated property
+    pub fn value_size(&self) -> usize {
+        let elements_per_key = match self.interpolation {
+            GltfAnimationInterpolation::CubicSpline => 3,
+            _ => 1,
+        };
+
+        if self.times.is_empty() {
+            0
+        } else {
+            self.values.len() / (self.times.len() * elements_per_key)
+        }
+    }
+
+    // `element` is only meaningful for cubic splines: 0 = in-tangent, 1 = value, 2 = out-tangent
+    fn key_element(&self, key: usize, element: usize) -> &[f32] {
+        let size = self.value_size();
+        let offset = match self.interpolation {
+            GltfAnimationInterpolation::CubicSpline => (key * 3 + element) * size,
+            _ => key * size,
+        };
+        &self.values[offset..offset + size]
+    }
+
+    fn key_value(&self, key: usize) -> &[f32] {
+        self.key_element(key, 1)
+    }
+
+    // Evaluates the channel at time `t`. Times outside of the keyframe range are clamped.
+    // Rotations are returned as `[x, y, z, w]` quaternions.
+    pub fn sample(&self, t: f32) -> Vec<f32> {
+        if self.times.is_empty() || self.value_size() == 0 {
+            return Vec::new();
+        }
+
+        let last = self.times.len() - 1;
+        if t <= self.times[0] {
+            return self.key_value(0).to_vec();
+        }
+        if t >= self.times[last] {
+            return self.key_value(last).to_vec();
+        }
+
+        let next = match self
+            .times
+            .binary_search_by(|k| k.partial_cmp(&t).unwrap_or(Ordering::Less))
+        {
+            Ok(key) => return self.key_value(key).to_vec(),
+            Err(next) => next,
+        };
+        let prev = next - 1;
+
+        let dt = self.times[next] - self.times[prev];
+        let s = (t - self.times[prev]) / dt;
+
+        match self.interpolation {
+            GltfAnimationInterpolation::Step => self.key_value(prev).to_vec(),
+            GltfAnimationInterpolation::Linear => {
+                let (a, b) = (self.key_value(prev), self.key_value(next));
+
+                if self.property == GltfAnimationProperty::Rotation && a.len() == 4 {
+                    let res = slerp_shortest(quat_from_xyzw(a), quat_from_xyzw(b), s);
+                    let q = res.quaternion();
+                    vec![q.i, q.j, q.k, q.w]
+                } else {
+                    a.iter()
+                        .zip(b.iter())
+                        .map(|(a, b)| a + (b - a) * s)
+                        .collect()
+                }
+            }
+            GltfAnimationInterpolation::CubicSpline => {
+                let v0 = self.key_value(prev);
+                let b0 = self.key_element(prev, 2);
+                let v1 = self.key_value(next);
+                let a1 = self.key_element(next, 0);
+
+                // Hermite basis, with tangents scaled by the keyframe delta as per the glTF spec
+                let s2 = s * s;
+                let s3 = s2 * s;
+                let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+                let h10 = (s3 - 2.0 * s2 + s) * dt;
+                let h01 = -2.0 * s3 + 3.0 * s2;
+                let h11 = (s3 - s2) * dt;
+
+                (0..v0.len())
+                    .map(|i| h00 * v0[i] + h10 * b0[i] + h01 * v1[i] + h11 * a1[i])
+                    .collect()
+            }
+        }
+    }
+}
+
+fn quat_from_xyzw(v: &[f32]) -> UnitQuaternion {
+    UnitQuaternion::from_quaternion(Quaternion::new(v[3], v[0], v[1], v[2]))
+}
+
+fn slerp_shortest(a: UnitQuaternion, b: UnitQuaternion, s: f32) -> UnitQuaternion {
+    let b = if a.coords.dot(&b.coords) < 0.0 {
+        UnitQuaternion::new_unchecked(-*b.quaternion())
+    } else {
+        b
+    };
+
+    a.try_slerp(&b, s, 1.0e-6).unwrap_or_else(|| a.nlerp(&b, s))
+}
+
+#[derive(Clone, Abomonation)]
+pub struct GltfAnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<GltfAnimationChannel>,
+    // Time of the last keyframe across all channels
+    pub duration: f32,
+}
+
+impl GltfAnimationClip {
+    // Overwrites the animated properties of `pose` with their values at time `t`
+    pub fn apply(&self, t: f32, pose: &mut GltfScenePose) {
+        for channel in self.channels.iter() {
+            let node = if let Some(node) = pose.nodes.get_mut(channel.node as usize) {
+                node
+            } else {
+                continue;
+            };
+
+            let v = channel.sample(t);
+
+            match channel.property {
+                GltfAnimationProperty::Translation if v.len() == 3 => {
+                    node.translation = Vector3::new(v[0], v[1], v[2]);
+                }
+                GltfAnimationProperty::Rotation if v.len() == 4 => {
+                    node.rotation = quat_from_xyzw(&v);
+                }
+                GltfAnimationProperty::Scale if v.len() == 3 => {
+                    node.scale = Vector3::new(v[0], v[1], v[2]);
+                }
+                GltfAnimationProperty::MorphWeights => {
+                    node.morph_weights = v;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GltfNodePose {
+    pub translation: Vector3,
+    pub rotation: UnitQuaternion,
+    pub scale: Vector3,
+    pub morph_weights: Vec<f32>,
+}
+
+impl GltfNodePose {
+    pub fn local_transform(&self) -> Matrix4 {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+// Local transforms and morph weights of every node in a `GltfScene`
+#[derive(Clone, Default)]
+pub struct GltfScenePose {
+    pub nodes: Vec<GltfNodePose>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(
+        property: GltfAnimationProperty,
+        interpolation: GltfAnimationInterpolation,
+        times: &[f32],
+        values: &[f32],
+    ) -> GltfAnimationChannel {
+        GltfAnimationChannel {
+            node: 0,
+            property,
+            interpolation,
+            times: times.to_vec(),
+            values: values.to_vec(),
+        }
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len(), "{:?} != {:?}", a, b);
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let c = channel(
+            GltfAnimationProperty::MorphWeights,
+            GltfAnimationInterpolation::Step,
+            &[0.0, 1.0, 2.0],
+            &[0.0, 10.0, 20.0],
+        );
+
+        assert_close(&c.sample(0.5), &[0.0]);
+        assert_close(&c.sample(1.0), &[10.0]);
+        assert_close(&c.sample(1.99), &[10.0]);
+    }
+
+    #[test]
+    fn linear_interpolates_every_component() {
+        let c = channel(
+            GltfAnimationProperty::Translation,
+            GltfAnimationInterpolation::Linear,
+            &[1.0, 3.0],
+            &[0.0, 2.0, -4.0, 4.0, 2.0, 4.0],
+        );
+
+        assert_eq!(c.value_size(), 3);
+        assert_close(&c.sample(1.5), &[1.0, 2.0, -2.0]);
+        assert_close(&c.sample(2.0), &[2.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn samples_outside_the_keys_are_clamped() {
+        for interpolation in [
+            GltfAnimationInterpolation::Step,
+            GltfAnimationInterpolation::Linear,
+        ]
+        .iter()
+        {
+            let c = channel(
+                GltfAnimationProperty::MorphWeights,
+                *interpolation,
+                &[1.0, 2.0],
+                &[5.0, 7.0],
+            );
+
+            assert_close(&c.sample(-1.0), &[5.0]);
+            assert_close(&c.sample(1.0), &[5.0]);
+            assert_close(&c.sample(2.0), &[7.0]);
+            assert_close(&c.sample(10.0), &[7.0]);
+        }
+
+        let c = channel(
+            GltfAnimationProperty::MorphWeights,
+            GltfAnimationInterpolation::CubicSpline,
+            &[1.0, 2.0],
+            &[9.0, 5.0, 9.0, 9.0, 7.0, 9.0],
+        );
+
+        assert_close(&c.sample(0.0), &[5.0]);
+        assert_close(&c.sample(3.0), &[7.0]);
+    }
+
+    #[test]
+    fn rotations_slerp_along_the_shortest_arc() {
+        let h = std::f32::consts::FRAC_PI_4;
+
+        // Identity to 90 degrees around +Z, with the second key given either way around
+        for sign in [1.0f32, -1.0].iter() {
+            let c = channel(
+                GltfAnimationProperty::Rotation,
+                GltfAnimationInterpolation::Linear,
+                &[0.0, 1.0],
+                &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, sign * h.sin(), sign * h.cos()],
+            );
+
+            let q = c.sample(0.5);
+            let expected = [0.0, 0.0, (h / 2.0).sin(), (h / 2.0).cos()];
+            assert_close(&q, &expected);
+        }
+    }
+
+    #[test]
+    fn cubic_splines_scale_tangents_by_the_key_delta() {
+        // Zero tangents ease in and out
+        let c = channel(
+            GltfAnimationProperty::MorphWeights,
+            GltfAnimationInterpolation::CubicSpline,
+            &[0.0, 1.0],
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        );
+        assert_close(&c.sample(0.25), &[0.15625]);
+        assert_close(&c.sample(0.5), &[0.5]);
+
+        // Only the out-tangent of the first key, over two seconds
+        let c = channel(
+            GltfAnimationProperty::MorphWeights,
+            GltfAnimationInterpolation::CubicSpline,
+            &[0.0, 2.0],
+            &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        );
+        assert_close(&c.sample(1.0), &[0.25]);
+    }
+}
@@ -201,6 +201,8 @@ pub fn create_texture(key: TextureKey) -> Texture {
 
 fn get_storage_compatible_format(f: vk::Format) -> vk::Format {
     match f {
+        vk::Format::R8_SRGB => vk::Format::R8_UNORM,
+        vk::Format::R8G8_SRGB => vk::Format::R8G8_UNORM,
         vk::Format::R8G8B8A8_SRGB => vk::Format::R8G8B8A8_UNORM,
         _ => f,
     }
@@ -0,0 +1,583 @@
+use super::*;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// Raster meshes baked by `rendertoy-bake`, stored as a table of streams, followed by their
+// contents. Every stream is either stored as-is, or deflate-compressed. The file is
+// memory-mapped, and streams get copied or decompressed directly into staging buffers.
+//
+// Layout, little-endian:
+//   magic: [u8; 8]
+//   version: u32
+//   stream_count: u32
+//   streams: [BakedMeshStreamDesc; stream_count]
+//   data
+const BAKED_MESH_MAGIC: [u8; 8] = *b"RTOYMESH";
+
+// Bump whenever the layout of the file, or of any of the streams changes,
+// e.g. `RasterGpuVertex`, `GpuMeshlet`, or `BakedMeshMetadata`.
+pub const BAKED_MESH_VERSION: u32 = 2;
+
+const BAKED_MESH_HEADER_SIZE: usize = 16;
+const BAKED_MESH_STREAM_DESC_SIZE: usize = 40;
+const BAKED_MESH_DATA_ALIGNMENT: usize = 16;
+
+const BAKED_MESH_COMPRESSION_NONE: u32 = 0;
+const BAKED_MESH_COMPRESSION_DEFLATE: u32 = 1;
+
+// The best case compression ratio of deflate. Streams claiming to inflate to more
+// than this are corrupt, and would otherwise make us allocate arbitrary amounts of memory.
+const BAKED_MESH_DEFLATE_MAX_RATIO: u64 = 1032;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum BakedMeshStream {
+    Vertices,
+    Uvs,
+    Colors,
+    Tangents,
+    Indices,
+    MaterialIds,
+    Meshlets,
+    MeshletVertices,
+    MeshletTriangles,
+    Metadata,
+}
+
+impl BakedMeshStream {
+    fn element_size(self) -> usize {
+        match self {
+            BakedMeshStream::Vertices => std::mem::size_of::<RasterGpuVertex>(),
+            BakedMeshStream::Uvs => 8,
+            BakedMeshStream::Colors => 16,
+            BakedMeshStream::Tangents => 16,
+            BakedMeshStream::Indices => 4,
+            BakedMeshStream::MaterialIds => 4,
+            BakedMeshStream::Meshlets => std::mem::size_of::<GpuMeshlet>(),
+            BakedMeshStream::MeshletVertices => 4,
+            BakedMeshStream::MeshletTriangles => 4,
+            BakedMeshStream::Metadata => 1,
+        }
+    }
+}
+
+// The position in this array is the stream's id in the file
+const BAKED_MESH_STREAMS: [BakedMeshStream; 10] = [
+    BakedMeshStream::Vertices,
+    BakedMeshStream::Uvs,
+    BakedMeshStream::Colors,
+    BakedMeshStream::Tangents,
+    BakedMeshStream::Indices,
+    BakedMeshStream::MaterialIds,
+    BakedMeshStream::Meshlets,
+    BakedMeshStream::MeshletVertices,
+    BakedMeshStream::MeshletTriangles,
+    BakedMeshStream::Metadata,
+];
+
+#[derive(Clone, Copy)]
+struct BakedMeshStreamDesc {
+    kind: u32,
+    compression: u32,
+    element_size: u32,
+    element_count: u64,
+    offset: u64,
+    stored_size: u64,
+}
+
+impl BakedMeshStreamDesc {
+    fn raw_size(&self) -> usize {
+        self.element_size as usize * self.element_count as usize
+    }
+}
+
+// Everything which isn't uploaded as-is
+#[derive(Clone, Abomonation)]
+struct BakedMeshMetadata {
+    materials: Vec<MeshMaterial>,
+    maps: Vec<MeshMaterialMap>,
+    aabb: Aabb,
+    material_aabbs: Vec<Aabb>,
+}
+
+pub struct BakedMesh {
+    mmap: memmap::Mmap,
+    streams: Vec<BakedMeshStreamDesc>,
+    pub materials: Vec<MeshMaterial>,
+    pub maps: Vec<MeshMaterialMap>,
+    pub aabb: Aabb,
+    pub material_aabbs: Vec<Aabb>,
+}
+
+fn slice_as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            data.as_ptr() as *const u8,
+            data.len() * std::mem::size_of::<T>(),
+        )
+    }
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64_le(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+impl BakedMesh {
+    fn stream_desc(&self, stream: BakedMeshStream) -> Result<&BakedMeshStreamDesc> {
+        let kind = BAKED_MESH_STREAMS
+            .iter()
+            .position(|s| *s == stream)
+            .unwrap() as u32;
+        self.streams
+            .iter()
+            .find(|desc| desc.kind == kind)
+            .ok_or_else(|| format_err!("Baked mesh stream {:?} not found", stream))
+    }
+
+    pub fn element_count(&self, stream: BakedMeshStream) -> usize {
+        self.stream_desc(stream)
+            .map(|desc| desc.element_count as usize)
+            .unwrap_or(0)
+    }
+
+    // Copies or decompresses the stream into `dst`, which must be at least as big
+    // as its uncompressed size. The rest of `dst` is zeroed.
+    // Sizes and offsets are validated by `parse`.
+    fn read_stream(&self, desc: &BakedMeshStreamDesc, dst: &mut [u8]) -> Result<()> {
+        let raw_size = desc.raw_size();
+        let src = &self.mmap[desc.offset as usize..(desc.offset + desc.stored_size) as usize];
+
+        match desc.compression {
+            BAKED_MESH_COMPRESSION_NONE => dst[..raw_size].copy_from_slice(src),
+            BAKED_MESH_COMPRESSION_DEFLATE => {
+                libflate::deflate::Decoder::new(src).read_exact(&mut dst[..raw_size])?
+            }
+            compression => return Err(format_err!("Unknown compression: {}", compression)),
+        }
+
+        for b in dst[raw_size..].iter_mut() {
+            *b = 0;
+        }
+
+        Ok(())
+    }
+
+    // Checks that the stream can be read without going out of bounds of the file or `dst`.
+    // Corrupt and stale files must not make `read_stream` panic.
+    fn validate_stream_desc(desc: &BakedMeshStreamDesc, file_size: usize) -> Result<()> {
+        let stream = *BAKED_MESH_STREAMS
+            .get(desc.kind as usize)
+            .ok_or_else(|| format_err!("Unknown baked mesh stream {}", desc.kind))?;
+
+        if desc.element_size as usize != stream.element_size() {
+            return Err(format_err!(
+                "Baked mesh stream {:?} has {}-byte elements (expected {}); re-bake it with rendertoy-bake",
+                stream,
+                desc.element_size,
+                stream.element_size()
+            ));
+        }
+
+        let raw_size = desc
+            .element_count
+            .checked_mul(u64::from(desc.element_size))
+            .filter(|size| *size <= std::usize::MAX as u64)
+            .ok_or_else(|| format_err!("Baked mesh stream {:?} is too large", stream))?;
+
+        match desc.offset.checked_add(desc.stored_size) {
+            Some(end) if end <= file_size as u64 => {}
+            _ => return Err(format_err!("Truncated baked mesh stream {:?}", stream)),
+        }
+
+        match desc.compression {
+            BAKED_MESH_COMPRESSION_NONE if desc.stored_size != raw_size => Err(format_err!(
+                "Baked mesh stream {:?} is {} bytes (expected {})",
+                stream,
+                desc.stored_size,
+                raw_size
+            )),
+            BAKED_MESH_COMPRESSION_DEFLATE
+                if raw_size / BAKED_MESH_DEFLATE_MAX_RATIO > desc.stored_size =>
+            {
+                Err(format_err!("Corrupt baked mesh stream {:?}", stream))
+            }
+            BAKED_MESH_COMPRESSION_NONE | BAKED_MESH_COMPRESSION_DEFLATE => Ok(()),
+            compression => Err(format_err!("Unknown compression: {}", compression)),
+        }
+    }
+
+    fn parse(mmap: memmap::Mmap) -> Result<BakedMesh> {
+        let data: &[u8] = &mmap;
+
+        if data.len() < BAKED_MESH_HEADER_SIZE || data[0..8] != BAKED_MESH_MAGIC {
+            return Err(format_err!("Not a baked mesh"));
+        }
+
+        let version = read_u32_le(data, 8);
+        if version != BAKED_MESH_VERSION {
+            return Err(format_err!(
+                "Baked mesh version {} is not supported (expected {}); re-bake it with rendertoy-bake",
+                version,
+                BAKED_MESH_VERSION
+            ));
+        }
+
+        let stream_count = read_u32_le(data, 12) as usize;
+        if data.len() < BAKED_MESH_HEADER_SIZE + stream_count * BAKED_MESH_STREAM_DESC_SIZE {
+            return Err(format_err!("Truncated baked mesh header"));
+        }
+
+        let mut streams = Vec::with_capacity(stream_count);
+        for i in 0..stream_count {
+            let offset = BAKED_MESH_HEADER_SIZE + i * BAKED_MESH_STREAM_DESC_SIZE;
+            let desc = BakedMeshStreamDesc {
+                kind: read_u32_le(data, offset),
+                compression: read_u32_le(data, offset + 4),
+                element_size: read_u32_le(data, offset + 8),
+                element_count: read_u64_le(data, offset + 16),
+                offset: read_u64_le(data, offset + 24),
+                stored_size: read_u64_le(data, offset + 32),
+            };
+
+            Self::validate_stream_desc(&desc, data.len())?;
+            streams.push(desc);
+        }
+
+        let mut res = BakedMesh {
+            mmap,
+            streams,
+            materials: Vec::new(),
+            maps: Vec::new(),
+            aabb: Aabb::empty(),
+            material_aabbs: Vec::new(),
+        };
+
+        let metadata_desc = *res.stream_desc(BakedMeshStream::Metadata)?;
+        let mut metadata_bytes = vec![0u8; metadata_desc.raw_size()];
+        res.read_stream(&metadata_desc, &mut metadata_bytes)?;
+
+        let metadata = unsafe { abomonation::decode::<BakedMeshMetadata>(&mut metadata_bytes) }
+            .map(|(metadata, _)| metadata.clone())
+            .ok_or_else(|| format_err!("Corrupt baked mesh metadata"))?;
+
+        res.materials = metadata.materials;
+        res.maps = metadata.maps;
+        res.aabb = metadata.aabb;
+        res.material_aabbs = metadata.material_aabbs;
+
+        Ok(res)
+    }
+}
+
+// Writes `mesh` in the format read by `load_baked_mesh`. The file is written to a temporary
+// path first, and then moved into place, so that it's safe to re-bake meshes
+// which are currently memory-mapped.
+pub fn write_baked_mesh(mesh: &RasterGpuMesh, path: &Path, compress: bool) -> Result<()> {
+    let mut metadata_bytes = Vec::new();
+    unsafe {
+        abomonation::encode(
+            &BakedMeshMetadata {
+                materials: mesh.materials.clone(),
+                maps: mesh.maps.clone(),
+                aabb: mesh.aabb,
+                material_aabbs: mesh.material_aabbs.clone(),
+            },
+            &mut metadata_bytes,
+        )?;
+    }
+
+    // (element count, contents) in the order of `BAKED_MESH_STREAMS`
+    let stream_contents: [(usize, &[u8]); 10] = [
+        (mesh.verts.len(), slice_as_bytes(&mesh.verts)),
+        (mesh.uvs.len(), slice_as_bytes(&mesh.uvs)),
+        (mesh.colors.len(), slice_as_bytes(&mesh.colors)),
+        (mesh.tangents.len(), slice_as_bytes(&mesh.tangents)),
+        (mesh.indices.len(), slice_as_bytes(&mesh.indices)),
+        (mesh.material_ids.len(), slice_as_bytes(&mesh.material_ids)),
+        (
+            mesh.meshlets.meshlets.len(),
+            slice_as_bytes(&mesh.meshlets.meshlets),
+        ),
+        (
+            mesh.meshlets.vertices.len(),
+            slice_as_bytes(&mesh.meshlets.vertices),
+        ),
+        (
+            mesh.meshlets.triangles.len(),
+            slice_as_bytes(&mesh.meshlets.triangles),
+        ),
+        (metadata_bytes.len(), &metadata_bytes),
+    ];
+
+    let mut descs = Vec::with_capacity(stream_contents.len());
+    let mut data: Vec<u8> = Vec::new();
+    let data_start = BAKED_MESH_HEADER_SIZE + stream_contents.len() * BAKED_MESH_STREAM_DESC_SIZE;
+
+    for (kind, (element_count, contents)) in stream_contents.iter().enumerate() {
+        while (data_start + data.len()) % BAKED_MESH_DATA_ALIGNMENT != 0 {
+            data.push(0);
+        }
+
+        let offset = data_start + data.len();
+        let compression = if compress {
+            let mut encoder = libflate::deflate::Encoder::new(Vec::new());
+            encoder.write_all(contents)?;
+            data.append(&mut encoder.finish().into_result()?);
+            BAKED_MESH_COMPRESSION_DEFLATE
+        } else {
+            data.extend_from_slice(contents);
+            BAKED_MESH_COMPRESSION_NONE
+        };
+
+        descs.push(BakedMeshStreamDesc {
+            kind: kind as u32,
+            compression,
+            element_size: BAKED_MESH_STREAMS[kind].element_size() as u32,
+            element_count: *element_count as u64,
+            offset: offset as u64,
+            stored_size: (data_start + data.len() - offset) as u64,
+        });
+    }
+
+    let mut header = Vec::with_capacity(data_start);
+    header.extend_from_slice(&BAKED_MESH_MAGIC);
+    header.extend_from_slice(&BAKED_MESH_VERSION.to_le_bytes());
+    header.extend_from_slice(&(descs.len() as u32).to_le_bytes());
+
+    for desc in descs.iter() {
+        header.extend_from_slice(&desc.kind.to_le_bytes());
+        header.extend_from_slice(&desc.compression.to_le_bytes());
+        header.extend_from_slice(&desc.element_size.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&desc.element_count.to_le_bytes());
+        header.extend_from_slice(&desc.offset.to_le_bytes());
+        header.extend_from_slice(&desc.stored_size.to_le_bytes());
+    }
+
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&header)?;
+        file.write_all(&data)?;
+    }
+    std::fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+#[snoozy]
+pub async fn load_baked_mesh_snoozy(ctx: Context, path: &AssetPath) -> Result<BakedMesh> {
+    let file_path = path.to_path_lossy(ctx.clone()).await?;
+    tracing::info!("Loading {}\n    -> {}", path, file_path);
+
+    let mmap = unsafe { memmap::Mmap::map(&File::open(&file_path)?)? };
+    crate::backend::file::watch_file(&file_path, ctx.get_invalidation_trigger());
+
+    BakedMesh::parse(mmap).map_err(|err| format_err!("{}: {}", path, err))
+}
+
+#[snoozy]
+pub async fn upload_baked_mesh_stream_snoozy(
+    mut ctx: Context,
+    mesh: &SnoozyRef<BakedMesh>,
+    stream: &BakedMeshStream,
+) -> Result<Buffer> {
+    let mesh = ctx.get(mesh).await?;
+    let desc = mesh.stream_desc(*stream)?;
+
+    // Vulkan doesn't allow empty buffers, so pad them with one zeroed element
+    let size_bytes = desc.raw_size().max(desc.element_size as usize);
+    upload_buffer_with_impl(size_bytes, None, |dst| mesh.read_stream(desc, dst))
+}
+
+// Same uniforms as `upload_raster_mesh`, for meshes baked with `rendertoy-bake`
+#[snoozy]
+pub async fn upload_baked_mesh_snoozy(
+    mut ctx: Context,
+    mesh_ref: &SnoozyRef<BakedMesh>,
+) -> Result<ShaderUniformBundle> {
+    let mesh = ctx.get(mesh_ref).await?;
+    let stream = |stream| upload_baked_mesh_stream(mesh_ref.clone(), stream);
+
+    let materials_buf = upload_mesh_materials(ctx.clone(), &mesh.materials, &mesh.maps).await;
+
+    Ok(shader_uniforms!(
+        mesh_vertex_buf: stream(BakedMeshStream::Vertices),
+        mesh_uv_buf: stream(BakedMeshStream::Uvs),
+        mesh_color_buf: stream(BakedMeshStream::Colors),
+        mesh_tangent_buf: stream(BakedMeshStream::Tangents),
+        mesh_index_count: mesh.element_count(BakedMeshStream::Indices) as u32,
+        mesh_index_buf: stream(BakedMeshStream::Indices),
+        mesh_material_id_buf: stream(BakedMeshStream::MaterialIds),
+        mesh_materials_buf: materials_buf,
+        mesh_meshlet_count: mesh.element_count(BakedMeshStream::Meshlets) as u32,
+        mesh_meshlet_buf: stream(BakedMeshStream::Meshlets),
+        mesh_meshlet_vertex_buf: stream(BakedMeshStream::MeshletVertices),
+        mesh_meshlet_triangle_buf: stream(BakedMeshStream::MeshletTriangles),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_mesh() -> RasterGpuMesh {
+        let mut mesh = TriangleMesh::default();
+        let material_id = mesh.add_default_material();
+
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+
+        for pos in positions.iter() {
+            mesh.positions.push(*pos);
+            mesh.normals.push([0.0, 0.0, 1.0]);
+            mesh.colors.push([1.0, 1.0, 1.0, 1.0]);
+            mesh.uvs.push([pos[0], 1.0 - pos[1]]);
+            mesh.tangents.push([1.0, 0.0, 0.0, 1.0]);
+            mesh.material_ids.push(material_id);
+        }
+        mesh.indices = vec![0, 1, 2, 0, 2, 3];
+
+        build_raster_mesh(&mesh).unwrap()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "rendertoy-baked-mesh-test-{}-{}.mesh",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn bake(name: &str, compress: bool) -> Vec<u8> {
+        let path = temp_path(name);
+        write_baked_mesh(&test_mesh(), &path, compress).unwrap();
+        let res = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        res
+    }
+
+    fn parse_bytes(name: &str, bytes: &[u8]) -> Result<BakedMesh> {
+        let path = temp_path(name);
+        std::fs::write(&path, bytes)?;
+        let mmap = unsafe { memmap::Mmap::map(&File::open(&path)?)? };
+        // Fails on Windows while mapped, which is fine for a temp file
+        let _ = std::fs::remove_file(&path);
+        BakedMesh::parse(mmap)
+    }
+
+    fn parse_error(name: &str, bytes: &[u8]) -> String {
+        match parse_bytes(name, bytes) {
+            Ok(_) => panic!("{}: corrupt baked mesh parsed successfully", name),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn read_stream(mesh: &BakedMesh, stream: BakedMeshStream) -> Vec<u8> {
+        let desc = mesh.stream_desc(stream).unwrap();
+        let mut res = vec![0xffu8; desc.raw_size()];
+        mesh.read_stream(desc, &mut res).unwrap();
+        res
+    }
+
+    fn stream_desc_offset(stream: BakedMeshStream) -> usize {
+        let kind = BAKED_MESH_STREAMS
+            .iter()
+            .position(|s| *s == stream)
+            .unwrap();
+        BAKED_MESH_HEADER_SIZE + kind * BAKED_MESH_STREAM_DESC_SIZE
+    }
+
+    #[test]
+    fn round_trip() {
+        let src = test_mesh();
+
+        for compress in [false, true].iter() {
+            let name = format!("round-trip-{}", compress);
+            let mesh = parse_bytes(&name, &bake(&name, *compress)).unwrap();
+
+            assert_eq!(
+                read_stream(&mesh, BakedMeshStream::Vertices),
+                slice_as_bytes(&src.verts)
+            );
+            assert_eq!(
+                read_stream(&mesh, BakedMeshStream::Uvs),
+                slice_as_bytes(&src.uvs)
+            );
+            assert_eq!(
+                read_stream(&mesh, BakedMeshStream::Indices),
+                slice_as_bytes(&src.indices)
+            );
+            assert_eq!(
+                read_stream(&mesh, BakedMeshStream::MeshletTriangles),
+                slice_as_bytes(&src.meshlets.triangles)
+            );
+            assert_eq!(mesh.element_count(BakedMeshStream::Indices), 6);
+
+            assert_eq!(mesh.materials.len(), src.materials.len());
+            assert_eq!(mesh.maps.len(), src.maps.len());
+            assert_eq!(mesh.aabb.min, src.aabb.min);
+            assert_eq!(mesh.aabb.max, src.aabb.max);
+            assert_eq!(mesh.material_aabbs.len(), src.material_aabbs.len());
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = bake("truncated", false);
+
+        let err = parse_error("truncated-data", &bytes[..bytes.len() - 1]);
+        assert!(err.contains("Truncated baked mesh stream"), "{}", err);
+
+        let err = parse_error("truncated-header", &bytes[..BAKED_MESH_HEADER_SIZE + 1]);
+        assert!(err.contains("Truncated baked mesh header"), "{}", err);
+
+        let err = parse_error("truncated-magic", &bytes[..4]);
+        assert!(err.contains("Not a baked mesh"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = bake("version", false);
+        bytes[8..12].copy_from_slice(&(BAKED_MESH_VERSION + 1).to_le_bytes());
+
+        let err = parse_error("version", &bytes);
+        assert!(err.contains("is not supported"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_wrong_element_sizes() {
+        let mut bytes = bake("element-size", false);
+        let offset = stream_desc_offset(BakedMeshStream::Uvs) + 8;
+        bytes[offset..offset + 4].copy_from_slice(&12u32.to_le_bytes());
+
+        let err = parse_error("element-size", &bytes);
+        assert!(err.contains("12-byte elements (expected 8)"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_deflate_streams_inflating_too_much() {
+        let mut bytes = bake("deflate-ratio", true);
+        let offset = stream_desc_offset(BakedMeshStream::Indices);
+        let stored_size = read_u64_le(&bytes, offset + 32);
+
+        // Four times the best case ratio of deflate
+        let element_count = stored_size * BAKED_MESH_DEFLATE_MAX_RATIO;
+        bytes[offset + 16..offset + 24].copy_from_slice(&element_count.to_le_bytes());
+
+        let err = parse_error("deflate-ratio", &bytes);
+        assert!(err.contains("Corrupt baked mesh stream Indices"), "{}", err);
+    }
+}
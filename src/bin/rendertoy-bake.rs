@@ -0,0 +1,87 @@
+// Converts glTF, OBJ, PLY and STL meshes to the format loaded by `load_baked_mesh`.
+// Like the rest of rendertoy, it resolves assets via cargo metadata, so it needs to run
+// in the directory of a crate which depends on rendertoy, e.g.:
+//
+//   cargo run --release --bin rendertoy-bake -- my_crate::meshes/scene.gltf assets/meshes/scene.rtmesh
+//
+// Plain file paths work as well.
+
+use rendertoy::*;
+use std::path::Path;
+use std::str::FromStr;
+
+fn main() {
+    tracing_subscriber::fmt::init();
+
+    let matches = clap::App::new("rendertoy-bake")
+        .about("Bakes meshes for rendertoy's load_baked_mesh")
+        .arg(
+            clap::Arg::with_name("input")
+                .help("Source mesh; an asset path such as my_crate::meshes/scene.gltf, or a file path")
+                .required(true),
+        )
+        .arg(
+            clap::Arg::with_name("output")
+                .help("Baked mesh file to write")
+                .required(true),
+        )
+        .arg(
+            clap::Arg::with_name("scale")
+                .long("scale")
+                .help("Uniform scale applied to the source mesh")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("uncompressed")
+                .long("uncompressed")
+                .help("Store streams without compression; larger, but faster to load"),
+        )
+        .get_matches();
+
+    let input = matches.value_of("input").unwrap();
+    let output = matches.value_of("output").unwrap();
+    let scale: f32 = matches
+        .value_of("scale")
+        .map(|val| FromStr::from_str(val).expect("Failed to parse scale"))
+        .unwrap_or(1.0);
+    let compress = !matches.is_present("uncompressed");
+
+    let path = if input.contains("::") {
+        rendertoy_asset_path("", input)
+    } else {
+        // Absolute paths replace the crate's asset directory in `AssetPath::to_path_lossy`
+        let file_path = std::fs::canonicalize(input).expect("Input file not found");
+        AssetPath {
+            crate_name: "rendertoy".to_string(),
+            asset_name: file_path.to_string_lossy().into_owned(),
+        }
+    };
+
+    let extension = Path::new(&path.asset_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    let mesh = match extension.as_str() {
+        "gltf" | "glb" => load_gltf_scene(path, scale),
+        "obj" => load_obj_scene(path, scale),
+        "ply" => load_ply_mesh(path, scale),
+        "stl" => load_stl_mesh(path, scale),
+        _ => panic!("Unsupported mesh format: {}", input),
+    };
+    let mesh = make_raster_mesh(optimize_mesh(mesh));
+
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let mesh = rt.block_on(async move {
+        let snapshot = get_snapshot(move |f| {
+            tokio::task::spawn(async move {
+                f();
+            });
+        });
+        snapshot.get(mesh).await
+    });
+
+    write_baked_mesh(&mesh, Path::new(output), compress).expect("Failed to write the baked mesh");
+    tracing::info!("Baked {} -> {}", input, output);
+}
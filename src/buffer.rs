@@ -74,9 +74,23 @@ where
     TCont: AsRef<[T]>,
 {
     let contents: &[T] = AsRef::<[T]>::as_ref(&**contents);
-    let size_of_t = size_of::<T>();
+    let size_bytes = contents.len() * size_of::<T>();
+
+    upload_buffer_with_impl(size_bytes, texture_format, |dst| {
+        dst.copy_from_slice(unsafe {
+            std::slice::from_raw_parts(contents.as_ptr() as *const u8, size_bytes)
+        });
+        Ok(())
+    })
+}
 
-    let size_bytes = contents.len() * size_of_t;
+// Creates a buffer of `size_bytes`, and lets `fill` write its contents directly into
+// the mapped staging memory, e.g. to decompress data without an intermediate copy.
+pub fn upload_buffer_with_impl(
+    size_bytes: usize,
+    texture_format: Option<vk::Format>,
+    fill: impl FnOnce(&mut [u8]) -> Result<()>,
+) -> Result<Buffer> {
     let res = backend::buffer::create_buffer(BufferKey::new(size_bytes, texture_format));
 
     let vk = vk();
@@ -99,18 +113,28 @@ where
             .expect("vma::create_buffer")
     };
 
-    unsafe {
+    let fill_result = unsafe {
         let mapped_ptr =
             vk.allocator
                 .map_memory(&staging_allocation)
                 .expect("mapping a staging buffer failed") as *mut std::ffi::c_void;
 
-        std::slice::from_raw_parts_mut(mapped_ptr as *mut u8, size_bytes).copy_from_slice(
-            &std::slice::from_raw_parts(contents.as_ptr() as *const u8, size_bytes),
-        );
+        let fill_result = fill(std::slice::from_raw_parts_mut(
+            mapped_ptr as *mut u8,
+            size_bytes,
+        ));
         vk.allocator
             .unmap_memory(&staging_allocation)
             .expect("unmap_memory");
+
+        fill_result
+    };
+
+    if let Err(err) = fill_result {
+        vk.allocator
+            .destroy_buffer(staging_buffer, &staging_allocation)
+            .unwrap();
+        return Err(err);
     }
 
     let copy_dst_buffer = res.buffer;
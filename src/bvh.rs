@@ -0,0 +1,438 @@
+use super::*;
+use futures::future::{BoxFuture, FutureExt};
+
+const BVH_BIN_COUNT: usize = 16;
+const BVH_MAX_LEAF_SIZE: usize = 8;
+
+// Traversal keeps a stack entry per level at most, so this must not exceed
+// `BVH_STACK_SIZE` in `assets/shaders/bvh.inc`. Deeper nodes are made into leaves.
+const BVH_MAX_DEPTH: usize = 32;
+
+// Relative to the cost of intersecting a triangle
+const BVH_TRAVERSAL_COST: f32 = 1.0;
+
+// Subtrees with more triangles than this get built in their own tasks
+const BVH_PARALLEL_BUILD_THRESHOLD: usize = 4096;
+
+// Must match `bvh_fetch_node` in `assets/shaders/bvh.inc`.
+// Leaves have a non-zero `triangle_count`, and their triangles start at `offset`.
+// Interior nodes are followed by their first child, and `offset` points to the second one.
+// A mesh without triangles gets a single root with both `offset` and `triangle_count` at zero.
+#[derive(Clone, Copy, Abomonation, Serialize)]
+#[repr(C)]
+pub struct GpuBvhNode {
+    pub box_min: [f32; 3],
+    pub offset: u32,
+    pub box_max: [f32; 3],
+    pub triangle_count: u32,
+}
+
+// Must match `bvh_fetch_triangle` in `assets/shaders/bvh.inc`.
+// Vertex positions are stored in BVH order, with the index of the source triangle in `v0`.
+#[derive(Clone, Copy, Abomonation, Serialize)]
+#[repr(C)]
+pub struct GpuBvhTriangle {
+    pub v0: [f32; 3],
+    pub triangle_index: u32,
+    pub v1: [f32; 3],
+    pub pad0: u32,
+    pub v2: [f32; 3],
+    pub pad1: u32,
+}
+
+#[derive(Clone, Abomonation)]
+pub struct Bvh {
+    pub nodes: Vec<GpuBvhNode>,
+    pub triangles: Vec<GpuBvhTriangle>,
+}
+
+#[derive(Clone, Copy)]
+struct BvhPrim {
+    aabb: Aabb,
+    centroid: [f32; 3],
+    triangle_index: u32,
+}
+
+enum BvhBuildNode {
+    Leaf {
+        aabb: Aabb,
+        triangles: Vec<u32>,
+    },
+    Interior {
+        aabb: Aabb,
+        children: Box<(BvhBuildNode, BvhBuildNode)>,
+    },
+}
+
+fn prims_aabb(prims: &[BvhPrim]) -> (Aabb, Aabb) {
+    let mut aabb = Aabb::empty();
+    let mut centroid_aabb = Aabb::empty();
+
+    for p in prims {
+        aabb = aabb.union(&p.aabb);
+        centroid_aabb.grow(&p.centroid);
+    }
+
+    (aabb, centroid_aabb)
+}
+
+// Finds the cheapest split according to the surface area heuristic, evaluated at bin boundaries.
+// Returns `None` if a leaf is cheaper. Otherwise, the prims get partitioned, and the number
+// of prims on the left side is returned. If all centroids coincide, and binning can't separate
+// the prims, too many of them for a leaf get split in half instead.
+fn partition_sah(prims: &mut [BvhPrim], aabb: &Aabb, centroid_aabb: &Aabb) -> Option<usize> {
+    if prims.len() <= 1 {
+        return None;
+    }
+
+    let mut best: Option<(f32, usize, usize)> = None;
+
+    for axis in 0..3 {
+        let extent = centroid_aabb.max[axis] - centroid_aabb.min[axis];
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let bin_scale = BVH_BIN_COUNT as f32 / extent;
+        let bin_of = |p: &BvhPrim| {
+            (((p.centroid[axis] - centroid_aabb.min[axis]) * bin_scale) as usize)
+                .min(BVH_BIN_COUNT - 1)
+        };
+
+        let mut bin_aabbs = [Aabb::empty(); BVH_BIN_COUNT];
+        let mut bin_counts = [0usize; BVH_BIN_COUNT];
+
+        for p in prims.iter() {
+            let bin = bin_of(p);
+            bin_aabbs[bin] = bin_aabbs[bin].union(&p.aabb);
+            bin_counts[bin] += 1;
+        }
+
+        // Sweep from the right to get the cost of everything past every split position
+        let mut right_costs = [0.0f32; BVH_BIN_COUNT];
+        {
+            let mut acc_aabb = Aabb::empty();
+            let mut acc_count = 0;
+            for bin in (1..BVH_BIN_COUNT).rev() {
+                acc_aabb = acc_aabb.union(&bin_aabbs[bin]);
+                acc_count += bin_counts[bin];
+                right_costs[bin] = acc_aabb.surface_area() * acc_count as f32;
+            }
+        }
+
+        let mut acc_aabb = Aabb::empty();
+        let mut acc_count = 0;
+        for split in 1..BVH_BIN_COUNT {
+            acc_aabb = acc_aabb.union(&bin_aabbs[split - 1]);
+            acc_count += bin_counts[split - 1];
+
+            if acc_count == 0 || acc_count == prims.len() {
+                continue;
+            }
+
+            let cost = acc_aabb.surface_area() * acc_count as f32 + right_costs[split];
+            if best
+                .map(|(best_cost, _, _)| cost < best_cost)
+                .unwrap_or(true)
+            {
+                best = Some((cost, axis, split));
+            }
+        }
+    }
+
+    let (best_cost, axis, split) = match best {
+        Some(best) => best,
+        None if prims.len() > BVH_MAX_LEAF_SIZE => return Some(prims.len() / 2),
+        None => return None,
+    };
+
+    let area = aabb.surface_area().max(std::f32::MIN_POSITIVE);
+    let split_cost = BVH_TRAVERSAL_COST + best_cost / area;
+    let leaf_cost = prims.len() as f32;
+
+    if split_cost >= leaf_cost && prims.len() <= BVH_MAX_LEAF_SIZE {
+        return None;
+    }
+
+    let extent = centroid_aabb.max[axis] - centroid_aabb.min[axis];
+    let bin_scale = BVH_BIN_COUNT as f32 / extent;
+    let goes_left = |p: &BvhPrim| {
+        (((p.centroid[axis] - centroid_aabb.min[axis]) * bin_scale) as usize).min(BVH_BIN_COUNT - 1)
+            < split
+    };
+
+    // In-place partition
+    let mut left_count = 0;
+    for i in 0..prims.len() {
+        if goes_left(&prims[i]) {
+            prims.swap(i, left_count);
+            left_count += 1;
+        }
+    }
+
+    Some(left_count)
+}
+
+fn make_leaf(aabb: Aabb, prims: &[BvhPrim]) -> BvhBuildNode {
+    BvhBuildNode::Leaf {
+        aabb,
+        triangles: prims.iter().map(|p| p.triangle_index).collect(),
+    }
+}
+
+fn partition_bvh_node(
+    prims: &mut [BvhPrim],
+    aabb: &Aabb,
+    centroid_aabb: &Aabb,
+    depth: usize,
+) -> Option<usize> {
+    if depth >= BVH_MAX_DEPTH {
+        None
+    } else {
+        partition_sah(prims, aabb, centroid_aabb)
+    }
+}
+
+fn build_bvh_node(prims: &mut [BvhPrim], depth: usize) -> BvhBuildNode {
+    let (aabb, centroid_aabb) = prims_aabb(prims);
+
+    if let Some(left_count) = partition_bvh_node(prims, &aabb, &centroid_aabb, depth) {
+        let (left, right) = prims.split_at_mut(left_count);
+        BvhBuildNode::Interior {
+            aabb,
+            children: Box::new((
+                build_bvh_node(left, depth + 1),
+                build_bvh_node(right, depth + 1),
+            )),
+        }
+    } else {
+        make_leaf(aabb, prims)
+    }
+}
+
+// Same as `build_bvh_node`, but large subtrees are built concurrently
+fn build_bvh_node_parallel(
+    mut prims: Vec<BvhPrim>,
+    depth: usize,
+) -> BoxFuture<'static, BvhBuildNode> {
+    async move {
+        if prims.len() <= BVH_PARALLEL_BUILD_THRESHOLD {
+            return build_bvh_node(&mut prims, depth);
+        }
+
+        let (aabb, centroid_aabb) = prims_aabb(&prims);
+
+        if let Some(left_count) = partition_bvh_node(&mut prims, &aabb, &centroid_aabb, depth) {
+            let right = prims.split_off(left_count);
+
+            let left = tokio::task::spawn(build_bvh_node_parallel(prims, depth + 1));
+            let right = build_bvh_node_parallel(right, depth + 1).await;
+            let left = left.await.expect("tokio join error");
+
+            BvhBuildNode::Interior {
+                aabb,
+                children: Box::new((left, right)),
+            }
+        } else {
+            make_leaf(aabb, &prims)
+        }
+    }
+    .boxed()
+}
+
+fn flatten_bvh_node(node: &BvhBuildNode, mesh: &TriangleMesh, res: &mut Bvh) {
+    let node_idx = res.nodes.len();
+
+    let (aabb, offset, triangle_count) = match node {
+        BvhBuildNode::Leaf { aabb, triangles } => {
+            let offset = res.triangles.len() as u32;
+
+            for tri in triangles.iter() {
+                let i = *tri as usize * 3;
+                let v = |k: usize| mesh.positions[mesh.indices[i + k] as usize];
+                res.triangles.push(GpuBvhTriangle {
+                    v0: v(0),
+                    triangle_index: *tri,
+                    v1: v(1),
+                    pad0: 0,
+                    v2: v(2),
+                    pad1: 0,
+                });
+            }
+
+            (*aabb, offset, triangles.len() as u32)
+        }
+        BvhBuildNode::Interior { aabb, .. } => (*aabb, 0, 0),
+    };
+
+    res.nodes.push(GpuBvhNode {
+        box_min: aabb.min,
+        offset,
+        box_max: aabb.max,
+        triangle_count,
+    });
+
+    if let BvhBuildNode::Interior { children, .. } = node {
+        flatten_bvh_node(&children.0, mesh, res);
+        res.nodes[node_idx].offset = res.nodes.len() as u32;
+        flatten_bvh_node(&children.1, mesh, res);
+    }
+}
+
+fn make_bvh_prims(mesh: &TriangleMesh) -> Vec<BvhPrim> {
+    // Point lists have no triangles to hit
+    if mesh.topology != MeshTopology::Triangles {
+        return Vec::new();
+    }
+
+    mesh.indices
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(triangle_index, tri)| {
+            let mut aabb = Aabb::empty();
+            for i in tri.iter() {
+                aabb.grow(&mesh.positions[*i as usize]);
+            }
+
+            BvhPrim {
+                aabb,
+                centroid: aabb.center(),
+                triangle_index: triangle_index as u32,
+            }
+        })
+        .collect()
+}
+
+fn flatten_bvh(root: &BvhBuildNode, mesh: &TriangleMesh) -> Bvh {
+    let mut res = Bvh {
+        nodes: Vec::new(),
+        triangles: Vec::new(),
+    };
+
+    // The root of an empty mesh is a leaf without triangles. Its inverted box would be
+    // hit by every ray after the min/max swap in `bvh_intersect_box`, so it gets an infinite
+    // one which can't be, and `bvh_intersect` also bails out on it by its zero `offset`.
+    if let BvhBuildNode::Leaf { triangles, .. } = root {
+        if triangles.is_empty() {
+            res.nodes.push(GpuBvhNode {
+                box_min: [std::f32::INFINITY; 3],
+                offset: 0,
+                box_max: [std::f32::NEG_INFINITY; 3],
+                triangle_count: 0,
+            });
+            return res;
+        }
+    }
+
+    flatten_bvh_node(root, mesh, &mut res);
+    res
+}
+
+// Builds a bounding volume hierarchy over the triangles of `mesh` for ray tracing in compute
+// shaders. Uses a binned surface area heuristic, with large subtrees built in parallel.
+#[snoozy(cache)]
+pub async fn build_bvh_snoozy(mut ctx: Context, mesh: &SnoozyRef<TriangleMesh>) -> Result<Bvh> {
+    let mesh = ctx.get(mesh).await?;
+
+    let root = build_bvh_node_parallel(make_bvh_prims(&mesh), 0).await;
+    Ok(flatten_bvh(&root, &mesh))
+}
+
+// Uploads the BVH as texel buffers for use with `assets/shaders/bvh.inc`. Their indices into
+// `all_buffers` end up in `bvh_node_buf_index` and `bvh_triangle_buf_index`.
+#[snoozy]
+pub async fn upload_bvh_snoozy(
+    mut ctx: Context,
+    bvh: &SnoozyRef<Bvh>,
+) -> Result<ShaderUniformBundle> {
+    let bvh = ctx.get(bvh).await?;
+
+    let node_buf = upload_array_tex_buffer(
+        ArcView::new(&bvh, |b| &b.nodes),
+        vk::Format::R32G32B32A32_UINT,
+    );
+
+    // Vulkan doesn't allow empty buffers
+    let triangle_buf = if bvh.triangles.is_empty() {
+        upload_array_tex_buffer(Box::new(vec![[0u32; 12]]), vk::Format::R32G32B32A32_UINT)
+    } else {
+        upload_array_tex_buffer(
+            ArcView::new(&bvh, |b| &b.triangles),
+            vk::Format::R32G32B32A32_UINT,
+        )
+    };
+
+    let node_buf_index = ctx.get(&node_buf).await?.bindless_index;
+    let triangle_buf_index = ctx.get(&triangle_buf).await?.bindless_index;
+
+    Ok(shader_uniforms!(
+        bvh_node_buf: node_buf,
+        bvh_node_buf_index: node_buf_index,
+        bvh_triangle_buf: triangle_buf,
+        bvh_triangle_buf_index: triangle_buf_index,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_bvh(mesh: &TriangleMesh) -> Bvh {
+        let mut prims = make_bvh_prims(mesh);
+        flatten_bvh(&build_bvh_node(&mut prims, 0), mesh)
+    }
+
+    fn grid_mesh(size: usize) -> TriangleMesh {
+        let mut mesh = TriangleMesh::default();
+        for y in 0..=size {
+            for x in 0..=size {
+                mesh.positions.push([x as f32, y as f32, 0.0]);
+            }
+        }
+
+        let row = size as u32 + 1;
+        for y in 0..size as u32 {
+            for x in 0..size as u32 {
+                let i = y * row + x;
+                mesh.indices
+                    .extend_from_slice(&[i, i + 1, i + row, i + 1, i + row + 1, i + row]);
+            }
+        }
+
+        mesh
+    }
+
+    #[test]
+    fn empty_mesh_gets_a_sentinel_root() {
+        let bvh = build_bvh(&TriangleMesh::default());
+
+        assert_eq!(bvh.nodes.len(), 1);
+        assert!(bvh.triangles.is_empty());
+
+        let root = &bvh.nodes[0];
+        assert_eq!(root.offset, 0);
+        assert_eq!(root.triangle_count, 0);
+        assert!((0..3).all(|i| root.box_min[i] > root.box_max[i]));
+    }
+
+    #[test]
+    fn every_triangle_ends_up_in_one_leaf() {
+        let mesh = grid_mesh(16);
+        let bvh = build_bvh(&mesh);
+
+        let mut seen = vec![false; mesh.indices.len() / 3];
+        for tri in bvh.triangles.iter() {
+            assert!(!seen[tri.triangle_index as usize]);
+            seen[tri.triangle_index as usize] = true;
+        }
+        assert!(seen.iter().all(|s| *s));
+
+        // Interior nodes point past their first child, and never back at the root
+        for (node_idx, node) in bvh.nodes.iter().enumerate() {
+            if node.triangle_count == 0 {
+                assert!(node.offset as usize > node_idx + 1);
+                assert!((node.offset as usize) < bvh.nodes.len());
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use crate::{na, na::UnitQuaternion, FrameState, Matrix4, Point3, Vector3, VirtualKeyCode};
+use crate::{
+    na, na::UnitQuaternion, record_camera, Aabb, CameraPathKey, CameraProjection, FrameState,
+    Matrix4, Point3, Vector2, Vector3, VirtualKeyCode,
+};
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize)]
 pub struct CameraMatrices {
     pub view_to_clip: Matrix4,
     pub clip_to_view: Matrix4,
@@ -15,6 +18,15 @@ pub trait Camera {
     fn calc_matrices(&self) -> CameraMatrices;
 }
 
+// Camera state which can be saved and restored, e.g. by `CameraBookmarks`
+pub trait SerializableCameraState {
+    type State: serde::Serialize + serde::de::DeserializeOwned;
+
+    fn save_state(&self) -> Self::State;
+    // Restores the state without smoothing
+    fn restore_state(&mut self, state: Self::State);
+}
+
 impl<T: Camera> From<&T> for CameraMatrices {
     fn from(camera: &T) -> CameraMatrices {
         camera.calc_matrices()
@@ -26,11 +38,9 @@ pub struct FirstPersonCamera {
     pub yaw: f32,
     pub pitch: f32,
     pub roll: f32,
-    pub fov: f32,
 
     pub position: Point3,
-    pub near_dist: f32,
-    pub aspect: f32,
+    pub projection: CameraProjection,
 
     pub interp_rot: UnitQuaternion<f32>,
     pub interp_pos: Point3,
@@ -38,6 +48,10 @@ pub struct FirstPersonCamera {
     pub move_smoothness: f32,
     pub look_smoothness: f32,
     pub move_speed: f32,
+
+    // Whether `update` feeds the camera path being recorded in the GUI. Off by default,
+    // so that only the camera which the app opts in gets recorded.
+    pub record_path: bool,
 }
 
 pub struct FirstPersonCameraInput {
@@ -45,6 +59,7 @@ pub struct FirstPersonCameraInput {
     yaw_delta: f32,
     pitch_delta: f32,
     dt: f32,
+    path_key: Option<CameraPathKey>,
 }
 
 impl<'a> From<&FrameState<'a>> for FirstPersonCameraInput {
@@ -87,6 +102,7 @@ impl<'a> From<&FrameState<'a>> for FirstPersonCameraInput {
             yaw_delta,
             pitch_delta,
             dt: frame_state.dt,
+            path_key: frame_state.camera_path_key,
         }
     }
 }
@@ -121,30 +137,112 @@ impl FirstPersonCamera {
         yaw_rot * (pitch_rot * roll_rot)
     }
 
+    // Sets yaw, pitch and roll to match `rotation`, skipping any smoothing
+    pub fn set_orientation(&mut self, rotation: UnitQuaternion<f32>) {
+        // `calc_rotation_quat` composes yaw (Y), pitch (X) and roll (Z), in that order
+        let m = *rotation.to_rotation_matrix().matrix();
+        self.pitch = (-m[(1, 2)]).max(-1.0).min(1.0).asin().to_degrees();
+        self.yaw = m[(0, 2)].atan2(m[(2, 2)]).to_degrees();
+        self.roll = m[(1, 0)].atan2(m[(1, 1)]).to_degrees();
+        self.interp_rot = self.calc_rotation_quat();
+    }
+
+    // Moves the camera without smoothing
+    pub fn set_position(&mut self, position: Point3) {
+        self.position = position;
+        self.interp_pos = position;
+    }
+
+    // Shorthands for `projection`, which replaced the `fov`, `near_dist` and `aspect` fields.
+    // The field of view is vertical, in degrees.
+    pub fn fov(&self) -> f32 {
+        self.projection.fov_y()
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.projection.set_fov_y(fov);
+    }
+
+    pub fn near_dist(&self) -> f32 {
+        self.projection.near()
+    }
+
+    pub fn set_near_dist(&mut self, near_dist: f32) {
+        self.projection.set_near(near_dist);
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.projection.aspect()
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.projection.set_aspect(aspect);
+    }
+
     pub fn new(position: Point3) -> FirstPersonCamera {
         FirstPersonCamera {
             yaw: 0_f32,
             pitch: 0_f32,
             roll: 0_f32,
-            fov: 45_f32,
             position,
-            near_dist: 0.1_f32,
-            aspect: 1.6_f32,
+            projection: CameraProjection::default(),
             interp_rot: UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -0.0f32.to_radians()),
             interp_pos: position,
             move_smoothness: 1.0,
             look_smoothness: 1.0,
             move_speed: 12.0,
+            record_path: false,
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct FirstPersonCameraState {
+    pub position: Point3,
+    // Degrees
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub projection: CameraProjection,
+}
+
+impl SerializableCameraState for FirstPersonCamera {
+    type State = FirstPersonCameraState;
+
+    fn save_state(&self) -> FirstPersonCameraState {
+        FirstPersonCameraState {
+            position: self.position,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            roll: self.roll,
+            projection: self.projection,
+        }
+    }
+
+    fn restore_state(&mut self, state: FirstPersonCameraState) {
+        self.set_position(state.position);
+        self.yaw = state.yaw;
+        self.pitch = state.pitch;
+        self.roll = state.roll;
+        self.interp_rot = self.calc_rotation_quat();
+        self.projection = state.projection;
+    }
+}
+
 impl Camera for FirstPersonCamera {
     type InputType = FirstPersonCameraInput;
 
     fn update<InputType: Into<Self::InputType>>(&mut self, input: InputType) {
         let input = input.into();
 
+        // Camera path playback skips input and smoothing
+        if let Some(key) = input.path_key {
+            self.set_position(key.position);
+            self.set_orientation(key.rotation);
+            self.interp_rot = key.rotation;
+            return;
+        }
+
         let move_dist = input.dt * 60.0;
         self.translate(&(input.move_vec * move_dist));
 
@@ -161,63 +259,294 @@ impl Camera for FirstPersonCamera {
             .coords
             .lerp(&self.position.coords, pos_interp)
             .into();
+
+        if self.record_path {
+            record_camera(&self.interp_pos, &self.interp_rot);
+        }
     }
 
     fn calc_matrices(&self) -> CameraMatrices {
-        let (view_to_clip, clip_to_view) = {
-            let fov = self.fov.to_radians();
-            let znear = self.near_dist;
-
-            let h = (0.5 * fov).cos() / (0.5 * fov).sin();
-            let w = h / self.aspect;
-
-            (
-                {
-                    let mut m = Matrix4::zeros();
-                    m.m11 = w;
-                    m.m22 = h;
-                    m.m34 = znear;
-                    m.m43 = -1.0;
-                    m
-                },
-                {
-                    let mut m = Matrix4::zeros();
-                    m.m11 = 1.0 / w;
-                    m.m22 = 1.0 / h;
-                    m.m34 = -1.0;
-                    m.m43 = 1.0 / znear;
-                    m
-                },
-            )
-        };
-
-        let rotation = self.interp_rot.to_homogeneous();
-
-        let view_to_world = {
-            let translation = Matrix4::new_translation(&Vector3::new(
-                self.interp_pos.x,
-                self.interp_pos.y,
-                self.interp_pos.z,
-            ));
-            translation * rotation
-        };
-
-        let world_to_view = {
-            let inv_translation = Matrix4::new_translation(&Vector3::new(
-                -self.interp_pos.x,
-                -self.interp_pos.y,
-                -self.interp_pos.z,
-            ));
-            rotation.transpose() * inv_translation
-        };
-
-        CameraMatrices {
-            view_to_clip,
-            clip_to_view,
-            world_to_view,
-            view_to_world,
+        calc_camera_matrices(&self.projection, &self.interp_pos, &self.interp_rot)
+    }
+}
+
+// Projection and view transforms for a camera at `position` looking down its local -Z axis
+fn calc_camera_matrices(
+    projection: &CameraProjection,
+    position: &Point3,
+    rotation: &UnitQuaternion<f32>,
+) -> CameraMatrices {
+    let (view_to_clip, clip_to_view) = projection.calc_clip_matrices();
+
+    let rotation = rotation.to_homogeneous();
+
+    let view_to_world = {
+        let translation =
+            Matrix4::new_translation(&Vector3::new(position.x, position.y, position.z));
+        translation * rotation
+    };
+
+    let world_to_view = {
+        let inv_translation =
+            Matrix4::new_translation(&Vector3::new(-position.x, -position.y, -position.z));
+        rotation.transpose() * inv_translation
+    };
+
+    CameraMatrices {
+        view_to_clip,
+        clip_to_view,
+        world_to_view,
+        view_to_world,
+    }
+}
+
+// Orbits around `target` at `distance`. Rotates with the right mouse button held,
+// pans with the middle one, and zooms with the mouse wheel.
+pub struct OrbitCamera {
+    // Degrees
+    pub yaw: f32,
+    pub pitch: f32,
+
+    pub target: Point3,
+    pub distance: f32,
+    pub projection: CameraProjection,
+
+    pub interp_rot: UnitQuaternion<f32>,
+    pub interp_target: Point3,
+    pub interp_distance: f32,
+
+    pub move_smoothness: f32,
+    pub look_smoothness: f32,
+    // Fraction of the distance zoomed per line scrolled
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+}
+
+pub struct OrbitCameraInput {
+    yaw_delta: f32,
+    pitch_delta: f32,
+    // In units of the viewport height
+    pan_delta: Vector2,
+    zoom_delta: f32,
+    dt: f32,
+}
+
+impl<'a> From<&FrameState<'a>> for OrbitCameraInput {
+    fn from(frame_state: &FrameState<'a>) -> OrbitCameraInput {
+        let mut yaw_delta = 0.0;
+        let mut pitch_delta = 0.0;
+        let mut pan_delta = Vector2::zeros();
+
+        if (frame_state.mouse.button_mask & 4) == 4 {
+            yaw_delta = -0.25 * frame_state.mouse.delta.x;
+            pitch_delta = -0.25 * frame_state.mouse.delta.y;
+        }
+
+        if (frame_state.mouse.button_mask & 2) == 2 {
+            let height = frame_state.window_size_pixels.1.max(1) as f32;
+            pan_delta = frame_state.mouse.delta / height;
+        }
+
+        OrbitCameraInput {
+            yaw_delta,
+            pitch_delta,
+            pan_delta,
+            zoom_delta: frame_state.mouse.wheel_delta,
+            dt: frame_state.dt,
+        }
+    }
+}
+
+impl OrbitCamera {
+    fn rotate_yaw(&mut self, delta: f32) {
+        self.yaw = (self.yaw + delta) % 720_f32;
+    }
+
+    fn rotate_pitch(&mut self, delta: f32) {
+        // Stop short of the poles, where yaw would flip around
+        self.pitch = (self.pitch + delta).max(-89.0).min(89.0);
+    }
+
+    fn calc_rotation_quat(&self) -> UnitQuaternion<f32> {
+        let yaw_rot: UnitQuaternion<f32> =
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.yaw.to_radians());
+        let pitch_rot: UnitQuaternion<f32> =
+            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.pitch.to_radians());
+        yaw_rot * pitch_rot
+    }
+
+    // Moves the target within the view plane, such that it follows the cursor
+    fn pan(&mut self, delta: &Vector2) {
+        let view_height = self.projection.view_size_at(self.distance).y;
+        let local_v = Vector3::new(-delta.x, delta.y, 0.0) * view_height;
+        self.target += self.calc_rotation_quat() * local_v;
+    }
+
+    // Orthographic projections don't change with distance, so their extents are scaled instead
+    fn zoom(&mut self, lines: f32) {
+        let prev_distance = self.distance;
+        self.distance =
+            (self.distance * (1.0 - self.zoom_speed).powf(lines)).max(self.min_distance);
+
+        if let CameraProjection::Orthographic {
+            left,
+            right,
+            bottom,
+            top,
+            ..
+        } = &mut self.projection
+        {
+            let scale = self.distance / prev_distance;
+            for extent in [left, right, bottom, top].iter_mut() {
+                **extent *= scale;
+            }
         }
     }
+
+    // Centers the view on `aabb`, and moves back far enough for its bounding sphere to fit.
+    // Orthographic projections are resized to fit it instead.
+    pub fn fit_to_bounds(&mut self, aabb: &Aabb) {
+        if aabb.is_empty() {
+            return;
+        }
+
+        let min = Point3::from(aabb.min);
+        let max = Point3::from(aabb.max);
+        let radius = 0.5 * (max - min).norm();
+
+        self.target = na::center(&min, &max);
+
+        if let CameraProjection::Orthographic { near, far, .. } = self.projection {
+            let view_size = self.projection.view_size_at(1.0);
+            let aspect = view_size.x / view_size.y;
+            let height = 2.0 * radius / aspect.min(1.0);
+
+            self.distance = (radius + near).max(self.min_distance);
+            let far = far.max(self.distance + radius);
+            self.projection = CameraProjection::orthographic(height, aspect, near, far);
+        } else {
+            let half_size = 0.5 * self.projection.view_size_at(1.0);
+            let half_fov = half_size.x.min(half_size.y).atan();
+
+            self.distance =
+                (radius / half_fov.sin() + self.projection.near()).max(self.min_distance);
+        }
+    }
+
+    // Moves the camera without smoothing
+    pub fn set_target(&mut self, target: Point3, distance: f32) {
+        self.target = target;
+        self.distance = distance;
+        self.interp_target = target;
+        self.interp_distance = distance;
+    }
+
+    pub fn calc_position(&self) -> Point3 {
+        self.interp_target + self.interp_rot * Vector3::new(0.0, 0.0, self.interp_distance)
+    }
+
+    // Same shorthands for `projection` as on `FirstPersonCamera`
+    pub fn fov(&self) -> f32 {
+        self.projection.fov_y()
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.projection.set_fov_y(fov);
+    }
+
+    pub fn near_dist(&self) -> f32 {
+        self.projection.near()
+    }
+
+    pub fn set_near_dist(&mut self, near_dist: f32) {
+        self.projection.set_near(near_dist);
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.projection.aspect()
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.projection.set_aspect(aspect);
+    }
+
+    pub fn new(target: Point3, distance: f32) -> OrbitCamera {
+        OrbitCamera {
+            yaw: 0_f32,
+            pitch: 0_f32,
+            target,
+            distance,
+            projection: CameraProjection::default(),
+            interp_rot: UnitQuaternion::identity(),
+            interp_target: target,
+            interp_distance: distance,
+            move_smoothness: 1.0,
+            look_smoothness: 1.0,
+            zoom_speed: 0.1,
+            min_distance: 0.01,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrbitCameraState {
+    pub target: Point3,
+    pub distance: f32,
+    // Degrees
+    pub yaw: f32,
+    pub pitch: f32,
+    pub projection: CameraProjection,
+}
+
+impl SerializableCameraState for OrbitCamera {
+    type State = OrbitCameraState;
+
+    fn save_state(&self) -> OrbitCameraState {
+        OrbitCameraState {
+            target: self.target,
+            distance: self.distance,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            projection: self.projection,
+        }
+    }
+
+    fn restore_state(&mut self, state: OrbitCameraState) {
+        self.set_target(state.target, state.distance);
+        self.yaw = state.yaw;
+        self.pitch = state.pitch;
+        self.interp_rot = self.calc_rotation_quat();
+        self.projection = state.projection;
+    }
+}
+
+impl Camera for OrbitCamera {
+    type InputType = OrbitCameraInput;
+
+    fn update<InputType: Into<Self::InputType>>(&mut self, input: InputType) {
+        let input = input.into();
+
+        self.rotate_pitch(input.pitch_delta);
+        self.rotate_yaw(input.yaw_delta);
+        self.pan(&input.pan_delta);
+        self.zoom(input.zoom_delta);
+
+        let target_quat = self.calc_rotation_quat();
+        let rot_interp = 1.0 - (-input.dt * 30.0 / self.look_smoothness.max(1e-5)).exp();
+        let pos_interp = 1.0 - (-input.dt * 16.0 / self.move_smoothness.max(1e-5)).exp();
+        self.interp_rot = self.interp_rot.slerp(&target_quat, rot_interp);
+        self.interp_rot.renormalize();
+        self.interp_target = self
+            .interp_target
+            .coords
+            .lerp(&self.target.coords, pos_interp)
+            .into();
+        self.interp_distance += (self.distance - self.interp_distance) * pos_interp;
+    }
+
+    fn calc_matrices(&self) -> CameraMatrices {
+        calc_camera_matrices(&self.projection, &self.calc_position(), &self.interp_rot)
+    }
 }
 
 pub struct CameraConvergenceEnforcer<CameraType: Camera> {
@@ -245,6 +574,20 @@ impl<CameraType: Camera> CameraConvergenceEnforcer<CameraType> {
     }
 }
 
+impl<CameraType: Camera + SerializableCameraState> SerializableCameraState
+    for CameraConvergenceEnforcer<CameraType>
+{
+    type State = CameraType::State;
+
+    fn save_state(&self) -> Self::State {
+        self.camera.save_state()
+    }
+
+    fn restore_state(&mut self, state: Self::State) {
+        self.camera.restore_state(state);
+    }
+}
+
 impl<CameraType: Camera> Camera for CameraConvergenceEnforcer<CameraType> {
     type InputType = CameraType::InputType;
 
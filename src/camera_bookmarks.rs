@@ -0,0 +1,280 @@
+use crate::{Camera, CameraMatrices, FrameState, SerializableCameraState, VirtualKeyCode};
+use imgui::{im_str, ImString};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Bookmarks are saved to the working directory, which is normally the project's crate
+const BOOKMARKS_FILE: &str = "camera_bookmarks.json";
+
+const HOTKEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    pub name: String,
+    // Number key which restores the bookmark, if any
+    pub hotkey: Option<u32>,
+    // `SerializableCameraState::State` of the camera it was saved from
+    pub state: serde_json::Value,
+}
+
+enum BookmarkRequest {
+    Save { hotkey: Option<u32> },
+    Restore { index: usize },
+}
+
+struct CameraBookmarksState {
+    file_path: PathBuf,
+    bookmarks: Vec<CameraBookmark>,
+    // Made by the GUI, and handled by `CameraBookmarks` on its next update
+    requests: Vec<BookmarkRequest>,
+}
+
+impl CameraBookmarksState {
+    fn load() -> Self {
+        let file_path = PathBuf::from(BOOKMARKS_FILE);
+        let bookmarks = std::fs::File::open(&file_path)
+            .ok()
+            .and_then(|file| {
+                serde_json::from_reader(std::io::BufReader::new(file))
+                    .map_err(|err| {
+                        tracing::warn!("Failed to parse {:?}: {}", file_path, err);
+                    })
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        Self {
+            file_path,
+            bookmarks,
+            requests: Vec::new(),
+        }
+    }
+
+    fn save(&self) {
+        let res = std::fs::File::create(&self.file_path)
+            .map_err(|err| format_err!("{}", err))
+            .and_then(|file| {
+                serde_json::to_writer_pretty(std::io::BufWriter::new(file), &self.bookmarks)
+                    .map_err(|err| format_err!("{}", err))
+            });
+
+        if let Err(err) = res {
+            tracing::error!("Failed to save {:?}: {}", self.file_path, err);
+        }
+    }
+}
+
+lazy_static! {
+    static ref CAMERA_BOOKMARKS: Mutex<CameraBookmarksState> =
+        { Mutex::new(CameraBookmarksState::load()) };
+}
+
+// Adds bookmarks to any camera with a `SerializableCameraState`. Ctrl+1..9 saves
+// the camera to a numbered bookmark, and 1..9 restores it. Bookmarks can also be added,
+// restored, renamed and deleted in the GUI.
+pub struct CameraBookmarks<CameraType> {
+    camera: CameraType,
+}
+
+impl<CameraType> CameraBookmarks<CameraType> {
+    pub fn new(camera: CameraType) -> Self {
+        Self { camera }
+    }
+
+    pub fn camera(&self) -> &CameraType {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut CameraType {
+        &mut self.camera
+    }
+}
+
+pub struct CameraBookmarksInput<InputType> {
+    camera_input: InputType,
+    save_hotkey: Option<u32>,
+    restore_hotkey: Option<u32>,
+}
+
+impl<'a, 'b, InputType> From<&'b FrameState<'a>> for CameraBookmarksInput<InputType>
+where
+    InputType: From<&'b FrameState<'a>>,
+{
+    fn from(frame_state: &'b FrameState<'a>) -> Self {
+        let keys = frame_state.keys;
+        let ctrl = keys.is_down(VirtualKeyCode::LControl) || keys.is_down(VirtualKeyCode::RControl);
+        let hotkey = HOTKEYS
+            .iter()
+            .position(|key| keys.was_pressed(*key))
+            .map(|i| i as u32 + 1);
+
+        CameraBookmarksInput {
+            camera_input: InputType::from(frame_state),
+            save_hotkey: hotkey.filter(|_| ctrl),
+            restore_hotkey: hotkey.filter(|_| !ctrl),
+        }
+    }
+}
+
+impl<CameraType> Camera for CameraBookmarks<CameraType>
+where
+    CameraType: Camera + SerializableCameraState,
+{
+    type InputType = CameraBookmarksInput<CameraType::InputType>;
+
+    fn update<InputType: Into<Self::InputType>>(&mut self, input: InputType) {
+        let input = input.into();
+        self.camera.update(input.camera_input);
+
+        let mut state = CAMERA_BOOKMARKS.lock().unwrap();
+        let state = &mut *state;
+        let mut requests: Vec<BookmarkRequest> = state.requests.drain(..).collect();
+
+        if let Some(hotkey) = input.save_hotkey {
+            requests.push(BookmarkRequest::Save {
+                hotkey: Some(hotkey),
+            });
+        }
+        if let Some(hotkey) = input.restore_hotkey {
+            if let Some(index) = state
+                .bookmarks
+                .iter()
+                .position(|b| b.hotkey == Some(hotkey))
+            {
+                requests.push(BookmarkRequest::Restore { index });
+            }
+        }
+
+        for request in requests {
+            match request {
+                BookmarkRequest::Save { hotkey } => {
+                    let camera_state = match serde_json::to_value(self.camera.save_state()) {
+                        Ok(camera_state) => camera_state,
+                        Err(err) => {
+                            tracing::error!("Failed to serialize the camera state: {}", err);
+                            continue;
+                        }
+                    };
+
+                    // Saving to a hotkey replaces its bookmark, but keeps the name
+                    let existing = hotkey.and_then(|hotkey| {
+                        state
+                            .bookmarks
+                            .iter_mut()
+                            .find(|b| b.hotkey == Some(hotkey))
+                    });
+
+                    if let Some(bookmark) = existing {
+                        bookmark.state = camera_state;
+                    } else {
+                        let name = match hotkey {
+                            Some(hotkey) => format!("Bookmark {}", hotkey),
+                            None => format!("Bookmark {}", state.bookmarks.len() + 1),
+                        };
+                        state.bookmarks.push(CameraBookmark {
+                            name,
+                            hotkey,
+                            state: camera_state,
+                        });
+                    }
+
+                    state.save();
+                }
+                BookmarkRequest::Restore { index } => {
+                    let bookmark = match state.bookmarks.get(index) {
+                        Some(bookmark) => bookmark,
+                        None => continue,
+                    };
+
+                    // Fails if the bookmark was saved from a different type of camera
+                    match serde_json::from_value(bookmark.state.clone()) {
+                        Ok(camera_state) => self.camera.restore_state(camera_state),
+                        Err(err) => tracing::warn!(
+                            "Bookmark {:?} doesn't match the camera: {}",
+                            bookmark.name,
+                            err
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    fn calc_matrices(&self) -> CameraMatrices {
+        self.camera.calc_matrices()
+    }
+}
+
+impl<CameraType: SerializableCameraState> SerializableCameraState for CameraBookmarks<CameraType> {
+    type State = CameraType::State;
+
+    fn save_state(&self) -> Self::State {
+        self.camera.save_state()
+    }
+
+    fn restore_state(&mut self, state: Self::State) {
+        self.camera.restore_state(state);
+    }
+}
+
+pub(crate) fn draw_gui(ui: &imgui::Ui) {
+    let mut state = CAMERA_BOOKMARKS.lock().unwrap();
+    let state = &mut *state;
+
+    // Requests not picked up since the last frame have no `CameraBookmarks` to handle them
+    state.requests.clear();
+
+    ui.text("Ctrl+1..9 saves, 1..9 restores");
+    if ui.button(im_str!("Add"), [0.0, 0.0]) {
+        state.requests.push(BookmarkRequest::Save { hotkey: None });
+    }
+
+    let mut renamed = false;
+    let mut deleted = None;
+
+    for (index, bookmark) in state.bookmarks.iter_mut().enumerate() {
+        match bookmark.hotkey {
+            Some(hotkey) => ui.text(format!("{}", hotkey)),
+            None => ui.text(" "),
+        }
+        ui.same_line(0.0);
+
+        let mut name = ImString::with_capacity(64);
+        name.push_str(&bookmark.name);
+        if ui
+            .input_text(&im_str!("##name{}", index), &mut name)
+            .build()
+        {
+            bookmark.name = name.to_str().to_owned();
+            renamed = true;
+        }
+
+        ui.same_line(0.0);
+        if ui.button(&im_str!("Go##{}", index), [0.0, 0.0]) {
+            state.requests.push(BookmarkRequest::Restore { index });
+        }
+
+        ui.same_line(0.0);
+        if ui.button(&im_str!("Delete##{}", index), [0.0, 0.0]) {
+            deleted = Some(index);
+        }
+    }
+
+    if let Some(index) = deleted {
+        state.bookmarks.remove(index);
+    }
+
+    if renamed || deleted.is_some() {
+        state.save();
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{Point3, UnitQuaternion};
+use imgui::im_str;
+use snoozy::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Playback advances by a fixed step per frame, so that fly-throughs
+// render the same frames regardless of performance.
+pub const CAMERA_PATH_PLAYBACK_DT: f32 = 1.0 / 60.0;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CameraPathKey {
+    // Seconds since the start of the path
+    pub time: f32,
+    pub position: Point3,
+    pub rotation: UnitQuaternion,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub keys: Vec<CameraPathKey>,
+}
+
+impl CameraPath {
+    pub fn load(path: &Path) -> Result<CameraPath> {
+        let file = std::fs::File::open(path)
+            .map_err(|err| format_err!("Failed to open {:?}: {}", path, err))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .map_err(|err| format_err!("Failed to create {:?}: {}", path, err))?;
+        Ok(serde_json::to_writer_pretty(
+            std::io::BufWriter::new(file),
+            self,
+        )?)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map(|key| key.time).unwrap_or(0.0)
+    }
+
+    // Catmull-Rom interpolation of positions, and slerp of rotations.
+    // Times outside of the path are clamped to its ends.
+    pub fn sample(&self, time: f32) -> Option<CameraPathKey> {
+        let keys = &self.keys;
+        let last = keys.len().checked_sub(1)?;
+
+        let next = keys
+            .iter()
+            .position(|key| key.time > time)
+            .unwrap_or(keys.len());
+        if 0 == next || next > last {
+            let key = keys[next.min(last)];
+            return Some(CameraPathKey { time, ..key });
+        }
+
+        let i = next - 1;
+        let k1 = &keys[i];
+        let k2 = &keys[next];
+        let t = ((time - k1.time) / (k2.time - k1.time).max(1e-10))
+            .max(0.0)
+            .min(1.0);
+
+        let p0 = keys[i.saturating_sub(1)].position.coords;
+        let p1 = k1.position.coords;
+        let p2 = k2.position.coords;
+        let p3 = keys[(next + 1).min(last)].position.coords;
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let position = 0.5
+            * ((2.0 * p1)
+                + (p2 - p0) * t
+                + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3);
+
+        // Take the shorter way around
+        let mut r2 = k2.rotation;
+        if k1.rotation.coords.dot(&r2.coords) < 0.0 {
+            r2 = UnitQuaternion::new_unchecked(-r2.into_inner());
+        }
+        let mut rotation = k1.rotation.slerp(&r2, t);
+        rotation.renormalize();
+
+        Some(CameraPathKey {
+            time,
+            position: position.into(),
+            rotation,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CameraPathMode {
+    Idle,
+    Recording { time: f32 },
+    Playing { time: f32 },
+}
+
+struct CameraPathState {
+    path: CameraPath,
+    file_path: PathBuf,
+    mode: CameraPathMode,
+    // Set when playback draws the last frame of the path; cleared when it's started again
+    finished_playback: bool,
+}
+
+lazy_static! {
+    static ref CAMERA_PATH: Mutex<CameraPathState> = {
+        Mutex::new(CameraPathState {
+            path: Default::default(),
+            file_path: PathBuf::from("camera_path.json"),
+            mode: CameraPathMode::Idle,
+            finished_playback: false,
+        })
+    };
+}
+
+// Called after updating cameras with `FirstPersonCamera::record_path` set, or directly by
+// apps with their own cameras. Only does anything while recording.
+pub fn record_camera(position: &Point3, rotation: &UnitQuaternion) {
+    let mut state = CAMERA_PATH.lock().unwrap();
+    if let CameraPathMode::Recording { time } = state.mode {
+        let key = CameraPathKey {
+            time,
+            position: *position,
+            rotation: *rotation,
+        };
+
+        // Keep one key per frame
+        match state.path.keys.last_mut() {
+            Some(last) if last.time == time => *last = key,
+            _ => state.path.keys.push(key),
+        }
+    }
+}
+
+// Loads the path from `file_path`, and starts playing it
+pub(crate) fn start_playback(file_path: &Path) -> Result<()> {
+    let path = CameraPath::load(file_path)?;
+    let mut state = CAMERA_PATH.lock().unwrap();
+    state.path = path;
+    state.file_path = file_path.to_owned();
+    state.mode = CameraPathMode::Playing { time: 0.0 };
+    state.finished_playback = false;
+    Ok(())
+}
+
+// Advances the recording or playback clock. During playback, returns the key which cameras
+// should snap to this frame; the frame should then use `CAMERA_PATH_PLAYBACK_DT`
+// in place of the wall-clock `dt`.
+pub(crate) fn begin_frame(dt: f32) -> Option<CameraPathKey> {
+    let mut state = CAMERA_PATH.lock().unwrap();
+    let state = &mut *state;
+
+    match state.mode {
+        CameraPathMode::Idle => None,
+        CameraPathMode::Recording { time } => {
+            // The first frame records the key at time zero
+            if !state.path.keys.is_empty() {
+                state.mode = CameraPathMode::Recording { time: time + dt };
+            }
+            None
+        }
+        CameraPathMode::Playing { time } => {
+            let key = state.path.sample(time);
+            let time = time + CAMERA_PATH_PLAYBACK_DT;
+
+            if key.is_none() || time > state.path.duration() + 0.5 * CAMERA_PATH_PLAYBACK_DT {
+                state.mode = CameraPathMode::Idle;
+                state.finished_playback = true;
+            } else {
+                state.mode = CameraPathMode::Playing { time };
+            }
+
+            key
+        }
+    }
+}
+
+// True once playback has drawn the last frame of the path
+pub(crate) fn is_playback_finished() -> bool {
+    CAMERA_PATH.lock().unwrap().finished_playback
+}
+
+pub(crate) fn draw_gui(ui: &imgui::Ui) {
+    let mut state = CAMERA_PATH.lock().unwrap();
+
+    ui.text(format!("File: {}", state.file_path.display()));
+    ui.text(format!(
+        "{} keys, {:.2}s",
+        state.path.keys.len(),
+        state.path.duration()
+    ));
+
+    match state.mode {
+        CameraPathMode::Idle => {
+            if ui.button(im_str!("Record"), [0.0, 0.0]) {
+                state.path = Default::default();
+                state.mode = CameraPathMode::Recording { time: 0.0 };
+            }
+            ui.same_line(0.0);
+            if ui.button(im_str!("Play"), [0.0, 0.0]) && !state.path.keys.is_empty() {
+                state.mode = CameraPathMode::Playing { time: 0.0 };
+                state.finished_playback = false;
+            }
+            ui.same_line(0.0);
+            if ui.button(im_str!("Save"), [0.0, 0.0]) {
+                if let Err(err) = state.path.save(&state.file_path) {
+                    tracing::error!("Failed to save the camera path: {}", err);
+                }
+            }
+            ui.same_line(0.0);
+            if ui.button(im_str!("Load"), [0.0, 0.0]) {
+                match CameraPath::load(&state.file_path) {
+                    Ok(path) => state.path = path,
+                    Err(err) => tracing::error!("Failed to load the camera path: {}", err),
+                }
+            }
+        }
+        CameraPathMode::Recording { time } => {
+            ui.text(format!("Recording: {:.2}s", time));
+            if state.path.keys.is_empty() {
+                ui.text("No camera is recording; set FirstPersonCamera::record_path");
+            }
+            if ui.button(im_str!("Stop recording"), [0.0, 0.0]) {
+                state.mode = CameraPathMode::Idle;
+            }
+        }
+        CameraPathMode::Playing { time } => {
+            ui.text(format!("Playing: {:.2}s", time));
+            if ui.button(im_str!("Stop"), [0.0, 0.0]) {
+                state.mode = CameraPathMode::Idle;
+            }
+        }
+    }
+}
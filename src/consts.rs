@@ -1,3 +1,4 @@
+use crate::CameraMatrices;
 use snoozy::*;
 
 #[snoozy]
@@ -9,3 +10,11 @@ pub async fn const_f32_snoozy(_ctx: Context, value: &f32) -> Result<f32> {
 pub async fn const_u32_snoozy(_ctx: Context, value: &u32) -> Result<u32> {
     Ok(*value)
 }
+
+#[snoozy]
+pub async fn const_camera_matrices_snoozy(
+    _ctx: Context,
+    value: &CameraMatrices,
+) -> Result<CameraMatrices> {
+    Ok(value.clone())
+}
@@ -0,0 +1,147 @@
+use super::*;
+
+#[derive(Clone, Copy, Abomonation)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: [std::f32::MAX; 3],
+            max: [-std::f32::MAX; 3],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    pub fn grow(&mut self, p: &[f32; 3]) {
+        for i in 0..3 {
+            self.min[i] = self.min[i].min(p[i]);
+            self.max[i] = self.max[i].max(p[i]);
+        }
+    }
+
+    // Component-wise, so that the union with an empty box is a no-op
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut res = *self;
+        for i in 0..3 {
+            res.min[i] = res.min[i].min(other.min[i]);
+            res.max[i] = res.max[i].max(other.max[i]);
+        }
+        res
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            0.5 * (self.min[0] + self.max[0]),
+            0.5 * (self.min[1] + self.max[1]),
+            0.5 * (self.min[2] + self.max[2]),
+        ]
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let d = [
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+            self.max[2] - self.min[2],
+        ];
+        2.0 * (d[0] * d[1] + d[1] * d[2] + d[2] * d[0])
+    }
+
+    // Conservative: only returns true if all corners end up on the outer side of the same
+    // clip plane. Expects the reverse-Z projections of `CameraMatrices`, with depth in [0, 1].
+    pub fn is_outside_frustum(&self, object_to_clip: &Matrix4) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let bounds = [&self.min, &self.max];
+        let mut outside = [true; 6];
+
+        for corner in 0..8 {
+            let p = Vector4::new(
+                bounds[corner & 1][0],
+                bounds[(corner >> 1) & 1][1],
+                bounds[(corner >> 2) & 1][2],
+                1.0,
+            );
+            let p = object_to_clip * p;
+
+            outside[0] &= p.x > p.w;
+            outside[1] &= p.x < -p.w;
+            outside[2] &= p.y > p.w;
+            outside[3] &= p.y < -p.w;
+            // In front of the near plane, or behind the camera
+            outside[4] &= p.z > p.w;
+            // Past the far plane, if the projection has one
+            outside[5] &= p.z < 0.0;
+        }
+
+        outside.iter().any(|o| *o)
+    }
+}
+
+impl RasterGpuMesh {
+    // Material bounds are only checked if the whole mesh is potentially visible;
+    // they are tighter than the mesh bounds when materials are spread apart.
+    // Meshes without materials have none, and are only culled by the mesh bounds.
+    pub fn is_outside_frustum(&self, object_to_clip: &Matrix4) -> bool {
+        self.aabb.is_outside_frustum(object_to_clip)
+            || (!self.material_aabbs.is_empty()
+                && self
+                    .material_aabbs
+                    .iter()
+                    .all(|aabb| aabb.is_outside_frustum(object_to_clip)))
+    }
+}
+
+// Like `upload_raster_scene`, but instances outside of the view frustum are left out,
+// so `raster_tex` doesn't record draws for them. The number of culled instances is passed
+// along in `raster_culled_instance_count`, and shows up in the GUI.
+// `camera` is meant to be redefined every frame, e.g. with `const_camera_matrices`.
+#[snoozy]
+pub async fn upload_culled_raster_scene_snoozy(
+    mut ctx: Context,
+    scene: &Vec<(SnoozyRef<TriangleMesh>, Vector3, UnitQuaternion)>,
+    camera: &SnoozyRef<CameraMatrices>,
+) -> Result<ShaderUniformBundle> {
+    let camera = ctx.get(camera).await?;
+    let world_to_clip = camera.view_to_clip * camera.world_to_view;
+
+    let mut res = Vec::with_capacity(scene.len() + 1);
+    let mut culled_count = 0u32;
+
+    for (mesh, position, rotation) in scene.iter() {
+        let raster_mesh = make_raster_mesh(mesh.clone());
+
+        let xform = Matrix4::new_translation(position) * rotation.to_homogeneous();
+        if ctx
+            .get(&raster_mesh)
+            .await?
+            .is_outside_frustum(&(world_to_clip * xform))
+        {
+            culled_count += 1;
+            continue;
+        }
+
+        res.push(shader_uniform_bundle!(
+            instance_transform: raster_mesh_transform(*position, *rotation),
+            :upload_raster_mesh(raster_mesh)
+        ));
+    }
+
+    res.push(ShaderUniformHolder::new(
+        "raster_culled_instance_count",
+        culled_count,
+    ));
+
+    Ok(res)
+}
@@ -0,0 +1,120 @@
+use crate::backend::texture::{create_texture, Texture, TextureKey};
+use crate::shader::dispatch_compute_tex;
+use crate::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Textures carried over from one frame to the next, e.g. for TAA and temporal denoising.
+// Holding on to a `Texture` keeps its allocation out of the transient resource pool,
+// so the previous frame's output can't be handed out as a new frame's render target.
+struct HistoryTexture {
+    // Stored last frame, and returned by `history_tex` this frame
+    prev: Option<Texture>,
+    // Stored this frame; becomes `prev` on the next one
+    current: Option<Texture>,
+}
+
+struct HistoryState {
+    frame: u64,
+    textures: HashMap<String, HistoryTexture>,
+}
+
+lazy_static! {
+    static ref HISTORY: Mutex<HistoryState> = {
+        Mutex::new(HistoryState {
+            frame: 0,
+            textures: HashMap::new(),
+        })
+    };
+}
+
+// Called before building every frame's graph
+pub(crate) fn begin_frame() {
+    let mut state = HISTORY.lock().unwrap();
+    state.frame += 1;
+
+    for tex in state.textures.values_mut() {
+        // Keep the old history if nothing was stored, e.g. when the graph didn't change
+        if let Some(current) = tex.current.take() {
+            tex.prev = Some(current);
+        }
+    }
+}
+
+// Releases the textures stored under `name`, e.g. when their owner goes away
+pub(crate) fn forget_history_tex(name: &str) {
+    HISTORY.lock().unwrap().textures.remove(name);
+}
+
+#[snoozy]
+pub async fn read_history_tex_snoozy(
+    mut ctx: Context,
+    name: &String,
+    key: &TextureKey,
+    _frame: &u64,
+) -> Result<Texture> {
+    let prev = HISTORY
+        .lock()
+        .unwrap()
+        .textures
+        .get(name)
+        .and_then(|tex| tex.prev.clone())
+        .filter(|tex| tex.key == *key);
+
+    if let Some(prev) = prev {
+        return Ok(prev);
+    }
+
+    // First frame, or the size or format changed
+    let cs = ctx
+        .get(&load_cs(crate::asset!("shaders/clear.glsl")))
+        .await?;
+    ctx.set_debug_name(&format!("history: {}", name));
+
+    let output_tex = create_texture(*key);
+    dispatch_compute_tex(&cs, &output_tex, Vec::new())?;
+    Ok(output_tex)
+}
+
+#[snoozy]
+pub async fn write_history_tex_snoozy(
+    mut ctx: Context,
+    name: &String,
+    tex: &SnoozyRef<Texture>,
+) -> Result<Texture> {
+    let tex = (*ctx.get(tex).await?).clone();
+
+    HISTORY
+        .lock()
+        .unwrap()
+        .textures
+        .entry(name.clone())
+        .or_insert_with(|| HistoryTexture {
+            prev: None,
+            current: None,
+        })
+        .current = Some(tex.clone());
+
+    Ok(tex)
+}
+
+// The texture stored under `name` by `store_history_tex` on the previous frame.
+// Returns a cleared texture on the first frame, and when `key` doesn't match
+// the stored texture's, e.g. after a resize.
+pub fn history_tex(name: &str, key: TextureKey) -> SnoozyRef<Texture> {
+    let frame = HISTORY.lock().unwrap().frame;
+    read_history_tex(name.to_owned(), key, frame)
+}
+
+// Keeps `tex` for the next frame's `history_tex(name, ..)`. The texture is only stored
+// once evaluated, so the returned reference should be used in place of `tex`, e.g.:
+//
+//   let prev = history_tex("taa", key);
+//   let taa = compute_tex(key, load_cs(asset!("shaders/taa.glsl")), shader_uniforms!(
+//       inputTex: color,
+//       historyTex: prev,
+//   ));
+//   store_history_tex("taa", taa)
+pub fn store_history_tex(name: &str, tex: SnoozyRef<Texture>) -> SnoozyRef<Texture> {
+    write_history_tex(name.to_owned(), tex)
+}
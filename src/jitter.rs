@@ -0,0 +1,218 @@
+use crate::{CameraMatrices, Vector2, ViewConstants};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum JitterSequence {
+    // Halton sequence in bases 2 and 3
+    Halton23,
+    // Roberts' R2 low-discrepancy sequence
+    R2,
+    // Best-candidate samples, ordered such that every run of consecutive frames
+    // is spread out evenly
+    BlueNoise,
+    // One sample per row and column of an N by N grid, with N being the period
+    NRooks,
+}
+
+impl JitterSequence {
+    // Offsets in pixels, in [-0.5, 0.5)
+    pub fn generate(self, period: u32) -> Vec<Vector2> {
+        let period = period.max(1);
+
+        match self {
+            JitterSequence::Halton23 => (0..period)
+                .map(|i| {
+                    // Skip the first element, which is (0, 0)
+                    Vector2::new(radical_inverse(i + 1, 2), radical_inverse(i + 1, 3))
+                        - Vector2::new(0.5, 0.5)
+                })
+                .collect(),
+            JitterSequence::R2 => {
+                // The plastic number, for which this sequence has the lowest discrepancy
+                let g = 1.324_717_957_244_746_f64;
+                let a = [1.0 / g, 1.0 / (g * g)];
+
+                (0..period)
+                    .map(|i| {
+                        let x = (0.5 + a[0] * f64::from(i)).fract();
+                        let y = (0.5 + a[1] * f64::from(i)).fract();
+                        Vector2::new(x as f32, y as f32) - Vector2::new(0.5, 0.5)
+                    })
+                    .collect()
+            }
+            JitterSequence::BlueNoise => best_candidate_samples(period),
+            JitterSequence::NRooks => {
+                let mut rng = XorShift32::new(0x9e37_79b9);
+                let mut rows: Vec<u32> = (0..period).collect();
+
+                // Fisher-Yates shuffle
+                for i in (1..period as usize).rev() {
+                    let j = (rng.next() % (i as u32 + 1)) as usize;
+                    rows.swap(i, j);
+                }
+
+                rows.iter()
+                    .enumerate()
+                    .map(|(column, row)| {
+                        Vector2::new(
+                            (column as f32 + 0.5) / period as f32,
+                            (*row as f32 + 0.5) / period as f32,
+                        ) - Vector2::new(0.5, 0.5)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn radical_inverse(mut i: u32, base: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut scale = inv_base;
+    let mut res = 0.0;
+
+    while i > 0 {
+        res += f64::from(i % base) * scale;
+        i /= base;
+        scale *= inv_base;
+    }
+
+    res as f32
+}
+
+// Mitchell's best-candidate algorithm. Each sample is the candidate furthest away
+// from the previous ones, so any prefix of the sequence is well distributed.
+// Distances wrap around, as jitter patterns tile across pixels.
+fn best_candidate_samples(count: u32) -> Vec<Vector2> {
+    const CANDIDATES_PER_SAMPLE: u32 = 32;
+
+    let mut rng = XorShift32::new(0x85eb_ca6b);
+    let mut res: Vec<Vector2> = Vec::with_capacity(count as usize);
+
+    let toroidal_dist2 = |a: &Vector2, b: &Vector2| {
+        let d = (a - b).abs();
+        let d = d.zip_map(&(Vector2::new(1.0, 1.0) - d), f32::min);
+        d.norm_squared()
+    };
+
+    for _ in 0..count {
+        let mut best = Vector2::zeros();
+        let mut best_dist2 = -1.0f32;
+
+        for _ in 0..CANDIDATES_PER_SAMPLE {
+            let candidate = Vector2::new(rng.next_f32(), rng.next_f32());
+            let dist2 = res
+                .iter()
+                .map(|p| toroidal_dist2(p, &candidate))
+                .fold(std::f32::MAX, f32::min);
+
+            if dist2 > best_dist2 {
+                best = candidate;
+                best_dist2 = dist2;
+            }
+        }
+
+        res.push(best);
+    }
+
+    res.into_iter()
+        .map(|p| p - Vector2::new(0.5, 0.5))
+        .collect()
+}
+
+// Fixed seeds keep sequences identical between runs
+struct XorShift32(u32);
+
+impl XorShift32 {
+    fn new(seed: u32) -> Self {
+        XorShift32(seed.max(1))
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        (self.next() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+// Builds jittered `ViewConstants` once per frame, and keeps the previous frame's
+// for motion vectors and reprojection, e.g.:
+//
+//   let view_constants = jitter.next_view_constants(&camera, width, height);
+//   let prev_view_constants = jitter.prev_view_constants();
+pub struct JitterProvider {
+    sequence: JitterSequence,
+    offsets: Vec<Vector2>,
+    frame_idx: u32,
+    view_constants: Option<ViewConstants>,
+    prev_view_constants: Option<ViewConstants>,
+}
+
+impl JitterProvider {
+    pub fn new(sequence: JitterSequence, period: u32) -> Self {
+        Self {
+            sequence,
+            offsets: sequence.generate(period),
+            frame_idx: 0,
+            view_constants: None,
+            prev_view_constants: None,
+        }
+    }
+
+    pub fn sequence(&self) -> JitterSequence {
+        self.sequence
+    }
+
+    pub fn period(&self) -> u32 {
+        self.offsets.len() as u32
+    }
+
+    // Restarts the sequence; previous view constants are kept
+    pub fn set_sequence(&mut self, sequence: JitterSequence, period: u32) {
+        if sequence != self.sequence || period.max(1) != self.period() {
+            self.sequence = sequence;
+            self.offsets = sequence.generate(period);
+            self.frame_idx = 0;
+        }
+    }
+
+    // Offset for the next call to `next_view_constants`, in pixels
+    pub fn current_offset(&self) -> Vector2 {
+        self.offsets[(self.frame_idx % self.period()) as usize]
+    }
+
+    pub fn next_view_constants<CamMat: Into<CameraMatrices>>(
+        &mut self,
+        camera_matrices: CamMat,
+        width: u32,
+        height: u32,
+    ) -> ViewConstants {
+        let view_constants = ViewConstants::build(camera_matrices, width, height)
+            .pixel_offset(self.current_offset())
+            .build();
+
+        self.frame_idx = self.frame_idx.wrapping_add(1);
+        self.prev_view_constants = self.view_constants.or(Some(view_constants));
+        self.view_constants = Some(view_constants);
+
+        view_constants
+    }
+
+    // The previous frame's view constants; the current ones on the first frame,
+    // so that reprojection starts out with zero motion.
+    pub fn prev_view_constants(&self) -> Option<&ViewConstants> {
+        self.prev_view_constants.as_ref()
+    }
+
+    // Forgets the previous frame, e.g. on camera cuts
+    pub fn reset(&mut self) {
+        self.frame_idx = 0;
+        self.view_constants = None;
+        self.prev_view_constants = None;
+    }
+}
@@ -27,6 +27,11 @@ impl KeyboardState {
         self.keys_down.get(&key)
     }
 
+    // True on the first frame the key is held down
+    pub fn was_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.get_down(key).map(|ks| 1 == ks.ticks).unwrap_or(false)
+    }
+
     pub fn iter_events(&self) -> impl Iterator<Item = &KeyboardInput> {
         self.events.iter()
     }
@@ -14,22 +14,43 @@ extern crate snoozy_macros;
 #[macro_use]
 extern crate abomonation_derive;
 
+mod accumulation;
+mod animation;
 mod backend;
+mod baked_mesh;
 mod blob;
 mod buffer;
+mod bvh;
 mod camera;
+mod camera_bookmarks;
+mod camera_path;
 mod consts;
+mod culling;
 mod dot;
 mod gpu_debugger;
 mod gpu_profiler;
 mod gui;
+mod history;
+mod jitter;
 mod keyboard;
 mod mesh;
+mod mesh_lod;
+mod mesh_optimizer;
+mod meshlet;
+mod obj;
 mod package;
+mod ply;
+mod primitives;
+mod projection;
+mod raster_stats;
+mod render_graph;
+mod render_pass_list;
 mod renderer;
 mod rendertoy;
 mod rgb9e5;
 mod shader;
+mod stl;
+mod tangent_space;
 mod texture;
 mod viewport;
 mod vk_backend_state;
@@ -39,18 +60,41 @@ mod warnings;
 
 pub mod compute_tex_macro;
 
+pub use self::accumulation::{accumulate_tex, accumulation_reset_token, ProgressiveAccumulation};
+pub use self::animation::*;
+pub use self::baked_mesh::*;
 pub use self::blob::*;
 pub use self::buffer::*;
+pub use self::bvh::*;
 pub use self::camera::*;
+pub use self::camera_bookmarks::{CameraBookmark, CameraBookmarks, CameraBookmarksInput};
+pub use self::camera_path::{record_camera, CameraPath, CameraPathKey, CAMERA_PATH_PLAYBACK_DT};
 pub use self::consts::*;
+pub use self::culling::*;
+pub use self::history::{history_tex, store_history_tex};
+pub use self::jitter::*;
 pub use self::keyboard::*;
 pub use self::mesh::*;
+pub use self::mesh_lod::*;
+pub use self::mesh_optimizer::*;
+pub use self::meshlet::*;
+pub use self::obj::*;
+pub use self::ply::*;
+pub use self::primitives::*;
+pub use self::projection::*;
+pub use self::render_graph::{
+    RenderGraph, RenderGraphBarrier, RenderGraphPass, RenderPassResources,
+};
+pub use self::render_pass_list::RenderPassListExt;
 pub use self::rendertoy::*;
 pub use self::rgb9e5::*;
 pub use self::shader::*;
+pub use self::stl::*;
+pub use self::tangent_space::*;
 pub use self::texture::*;
 pub use self::viewport::*;
 pub use ash::{vk, vk::Format};
+pub use imgui;
 pub use nalgebra as na;
 pub use snoozy::*;
 pub use warnings::rtoy_show_warning;
@@ -81,6 +125,23 @@ pub trait RenderPass: Any {
         frame_state: &FrameState,
         frame_idx: u32,
     );
+
+    // Resources read and written by the pass, for ordering and culling in a `RenderGraph`.
+    // Passes which don't declare any are run in list order, and never culled.
+    fn declare_resources(&self, _resources: &mut RenderPassResources) {}
+
+    // Shown in the GUI, and used to remember whether the pass is enabled
+    fn name(&self) -> String {
+        let type_name = std::any::type_name::<Self>();
+        type_name
+            .rsplit("::")
+            .next()
+            .unwrap_or(type_name)
+            .to_owned()
+    }
+
+    // Per-pass settings, drawn under the pass in the render pass list
+    fn draw_gui(&mut self, _ui: &imgui::Ui) {}
 }
 
 use std::any::Any;
@@ -117,6 +178,45 @@ where
     }
 }
 
+// Overrides the name of a pass, e.g. for closures, which are otherwise called `{{closure}}`
+pub struct NamedRenderPass<P> {
+    name: String,
+    pub pass: P,
+}
+
+impl<P> NamedRenderPass<P> {
+    pub fn new(name: &str, pass: P) -> Self {
+        Self {
+            name: name.to_owned(),
+            pass,
+        }
+    }
+}
+
+impl<P: RenderPass> RenderPass for NamedRenderPass<P> {
+    fn prepare_frame(
+        &mut self,
+        view_constants: &ViewConstants,
+        frame_state: &FrameState,
+        frame_idx: u32,
+    ) {
+        self.pass
+            .prepare_frame(view_constants, frame_state, frame_idx)
+    }
+
+    fn declare_resources(&self, resources: &mut RenderPassResources) {
+        self.pass.declare_resources(resources)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn draw_gui(&mut self, ui: &imgui::Ui) {
+        self.pass.draw_gui(ui)
+    }
+}
+
 pub type RenderPassList = Vec<Box<dyn RenderPassAny>>;
 
 // Convenient .add method which adds a trait object to the render pass list,
@@ -125,8 +225,12 @@ pub type RenderPassList = Vec<Box<dyn RenderPassAny>>;
 // let ao_tex = sub_passes
 //     .add(rtoy_samples::ssao::Ssao::new(tex_key, gbuffer_tex.clone()))
 //     .get_output_tex();
+//
+// `add_named` does the same, but gives the pass a name for the GUI. Unique names
+// keep passes' enabled flags attached to them when the list is rebuilt in a different order.
 pub trait AddRenderPass {
     fn add<P: RenderPassAny + 'static>(&mut self, pass: P) -> &P;
+    fn add_named<P: RenderPassAny + 'static>(&mut self, name: &str, pass: P) -> &P;
 }
 
 impl AddRenderPass for RenderPassList {
@@ -134,4 +238,8 @@ impl AddRenderPass for RenderPassList {
         self.push(Box::new(pass));
         self.last().unwrap().as_any().downcast_ref::<P>().unwrap()
     }
+
+    fn add_named<P: RenderPassAny + 'static>(&mut self, name: &str, pass: P) -> &P {
+        &self.add(NamedRenderPass::new(name, pass)).pass
+    }
 }
@@ -3,15 +3,65 @@ use futures::future::try_join_all;
 
 #[derive(Clone, Abomonation)]
 pub enum MeshMaterialMap {
-    Asset { path: AssetPath, params: TexParams },
+    Asset {
+        path: AssetPath,
+        params: TexParams,
+    },
+    InMemory {
+        image: InMemoryImage,
+        params: TexParams,
+    },
     Placeholder([u8; 4]),
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Abomonation)]
+pub enum MeshAlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+// Indices into `MeshMaterial::maps` and `MeshMaterial::map_transforms`
+pub const MESH_MATERIAL_MAP_NORMAL: usize = 0;
+pub const MESH_MATERIAL_MAP_METALLIC_ROUGHNESS: usize = 1;
+pub const MESH_MATERIAL_MAP_ALBEDO: usize = 2;
+pub const MESH_MATERIAL_MAP_EMISSIVE: usize = 3;
+pub const MESH_MATERIAL_MAP_OCCLUSION: usize = 4;
+pub const MESH_MATERIAL_MAP_COUNT: usize = 5;
+
+// Row-major 2x3 affine UV transform, as per KHR_texture_transform
+pub const IDENTITY_UV_TRANSFORM: [f32; 6] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
 #[derive(Clone, Abomonation)]
 pub struct MeshMaterial {
     pub base_color_mult: [f32; 4],
-    pub maps: [u32; 3],
+    pub maps: [u32; MESH_MATERIAL_MAP_COUNT],
+    pub map_transforms: [[f32; 6]; MESH_MATERIAL_MAP_COUNT],
     pub emissive: [f32; 3],
+    pub metallic_mult: f32,
+    pub roughness_mult: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub alpha_mode: MeshAlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+    // The metallic-roughness map holds an OBJ `map_Ns` specular exponent scale instead.
+    // See `mesh_material_roughness` in `assets/shaders/mesh_material.inc`.
+    pub specular_exponent_map: bool,
+}
+
+// How the indices of a `TriangleMesh` make up primitives
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Abomonation)]
+pub enum MeshTopology {
+    Triangles,
+    // One index per point, as in PLY point clouds. Drawn with `RasterTopology::Points`.
+    Points,
+}
+
+impl Default for MeshTopology {
+    fn default() -> Self {
+        MeshTopology::Triangles
+    }
 }
 
 #[derive(Abomonation, Clone, Default)]
@@ -25,32 +75,86 @@ pub struct TriangleMesh {
     pub indices: Vec<u32>,
     pub materials: Vec<MeshMaterial>, // global
     pub maps: Vec<MeshMaterialMap>,   // global
+    pub topology: MeshTopology,
 }
 
-fn iter_gltf_node_tree<F: FnMut(&gltf::scene::Node, Matrix4)>(
-    node: &gltf::scene::Node,
-    xform: Matrix4,
-    f: &mut F,
-) {
-    let node_xform: Matrix4 = node.transform().matrix().into();
-    let xform = xform * node_xform;
+enum GltfTextureSource {
+    Uri(String),
+    Embedded(InMemoryImage),
+}
 
-    f(&node, xform);
-    for child in node.children() {
-        iter_gltf_node_tree(&child, xform, f);
+fn get_gltf_texture_source(
+    tex: gltf::texture::Texture,
+    buffers: &[gltf::buffer::Data],
+) -> Option<GltfTextureSource> {
+    match tex.source().source() {
+        gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+            let encoded = uri
+                .find(";base64,")
+                .and_then(|idx| base64::decode(&uri[idx + ";base64,".len()..]).ok())?;
+            Some(GltfTextureSource::Embedded(InMemoryImage::new(encoded)))
+        }
+        gltf::image::Source::Uri { uri, .. } => Some(GltfTextureSource::Uri(uri.to_string())),
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            let contents = &buffer[view.offset()..view.offset() + view.length()];
+            Some(GltfTextureSource::Embedded(InMemoryImage::new(
+                contents.to_vec(),
+            )))
+        }
     }
 }
 
-fn get_gltf_texture_source(tex: gltf::texture::Texture) -> Option<String> {
-    match tex.source().source() {
-        gltf::image::Source::Uri { uri, .. } => Some(uri.to_string()),
-        _ => None,
+// gltf-json drops extensions it doesn't know about, so those are looked up in the raw document.
+fn load_gltf_raw_json(path: &str) -> Result<serde_json::Value> {
+    let contents = std::fs::read(path)?;
+
+    if contents.starts_with(b"glTF") {
+        let glb = gltf::Glb::from_slice(&contents)?;
+        Ok(serde_json::from_slice(&glb.json)?)
+    } else {
+        Ok(serde_json::from_slice(&contents)?)
     }
 }
 
+// KHR_texture_transform on a textureInfo object. Only UV set 0 is loaded,
+// so any `texCoord` override is ignored.
+fn get_gltf_uv_transform(texture_info: &serde_json::Value) -> [f32; 6] {
+    let xform = &texture_info["extensions"]["KHR_texture_transform"];
+    if xform.is_null() {
+        return IDENTITY_UV_TRANSFORM;
+    }
+
+    let get_f32 =
+        |v: &serde_json::Value, default: f32| v.as_f64().map(|v| v as f32).unwrap_or(default);
+
+    let offset = [
+        get_f32(&xform["offset"][0], 0.0),
+        get_f32(&xform["offset"][1], 0.0),
+    ];
+    let scale = [
+        get_f32(&xform["scale"][0], 1.0),
+        get_f32(&xform["scale"][1], 1.0),
+    ];
+    let rotation = get_f32(&xform["rotation"], 0.0);
+
+    // translation * rotation * scale
+    let (sin, cos) = rotation.sin_cos();
+    [
+        cos * scale[0],
+        sin * scale[1],
+        offset[0],
+        -sin * scale[0],
+        cos * scale[1],
+        offset[1],
+    ]
+}
+
 fn load_gltf_material(
     mat: &gltf::material::Material,
     parent_path: &AssetPath,
+    buffers: &[gltf::buffer::Data],
+    raw_json: &serde_json::Value,
 ) -> (Vec<MeshMaterialMap>, MeshMaterial) {
     let make_asset_path = |path: String| -> AssetPath {
         let mut asset_name: std::path::PathBuf = parent_path.asset_name.clone().into();
@@ -62,180 +166,1005 @@ fn load_gltf_material(
         }
     };
 
-    let make_material_map = |path: String| -> MeshMaterialMap {
-        MeshMaterialMap::Asset {
-            path: make_asset_path(path),
-            params: TexParams {
-                gamma: TexGamma::Linear,
-            },
+    let make_material_map = |gamma: TexGamma, channels: TexChannels| {
+        move |source: GltfTextureSource| -> MeshMaterialMap {
+            let params = TexParams {
+                gamma,
+                channels,
+                bit_depth: TexBitDepth::U8,
+            };
+
+            match source {
+                GltfTextureSource::Uri(path) => MeshMaterialMap::Asset {
+                    path: make_asset_path(path),
+                    params,
+                },
+                GltfTextureSource::Embedded(image) => MeshMaterialMap::InMemory { image, params },
+            }
         }
     };
 
-    let albedo_map = mat
-        .pbr_metallic_roughness()
+    let pbr = mat.pbr_metallic_roughness();
+
+    let albedo_map = pbr
         .base_color_texture()
         .and_then(|tex| {
-            get_gltf_texture_source(tex.texture()).map(|path: String| -> MeshMaterialMap {
-                MeshMaterialMap::Asset {
-                    path: make_asset_path(path),
-                    params: TexParams {
-                        gamma: TexGamma::Srgb,
-                    },
-                }
-            })
+            get_gltf_texture_source(tex.texture(), buffers)
+                .map(make_material_map(TexGamma::Srgb, TexChannels::Rgba))
         })
         .unwrap_or(MeshMaterialMap::Placeholder([127, 127, 127, 255]));
 
     let normal_map = mat
         .normal_texture()
-        .and_then(|tex| get_gltf_texture_source(tex.texture()).map(make_material_map))
+        .and_then(|tex| {
+            get_gltf_texture_source(tex.texture(), buffers)
+                .map(make_material_map(TexGamma::Linear, TexChannels::Rgba))
+        })
         .unwrap_or(MeshMaterialMap::Placeholder([127, 127, 255, 255]));
 
-    let spec_map = mat
-        .pbr_metallic_roughness()
+    let spec_map = pbr
         .metallic_roughness_texture()
-        .and_then(|tex| get_gltf_texture_source(tex.texture()).map(make_material_map))
+        .and_then(|tex| {
+            get_gltf_texture_source(tex.texture(), buffers)
+                .map(make_material_map(TexGamma::Linear, TexChannels::Rgba))
+        })
         .unwrap_or(MeshMaterialMap::Placeholder([127, 127, 0, 255]));
 
-    let emissive = if mat.emissive_texture().is_some() {
-        [0.0, 0.0, 0.0]
-    } else {
-        mat.emissive_factor()
+    // Multiplied by the emissive factor, so a white placeholder leaves the factor as-is.
+    let emissive_map = mat
+        .emissive_texture()
+        .and_then(|tex| {
+            get_gltf_texture_source(tex.texture(), buffers)
+                .map(make_material_map(TexGamma::Srgb, TexChannels::Rgba))
+        })
+        .unwrap_or(MeshMaterialMap::Placeholder([255, 255, 255, 255]));
+
+    // Occlusion only lives in the red channel; it's often packed with metallic-roughness.
+    let occlusion_map = mat
+        .occlusion_texture()
+        .and_then(|tex| {
+            get_gltf_texture_source(tex.texture(), buffers)
+                .map(make_material_map(TexGamma::Linear, TexChannels::R))
+        })
+        .unwrap_or(MeshMaterialMap::Placeholder([255, 255, 255, 255]));
+
+    let mat_json = mat
+        .index()
+        .map(|idx| &raw_json["materials"][idx])
+        .unwrap_or(&serde_json::Value::Null);
+
+    let mut map_transforms = [IDENTITY_UV_TRANSFORM; MESH_MATERIAL_MAP_COUNT];
+    map_transforms[MESH_MATERIAL_MAP_NORMAL] = get_gltf_uv_transform(&mat_json["normalTexture"]);
+    map_transforms[MESH_MATERIAL_MAP_METALLIC_ROUGHNESS] =
+        get_gltf_uv_transform(&mat_json["pbrMetallicRoughness"]["metallicRoughnessTexture"]);
+    map_transforms[MESH_MATERIAL_MAP_ALBEDO] =
+        get_gltf_uv_transform(&mat_json["pbrMetallicRoughness"]["baseColorTexture"]);
+    map_transforms[MESH_MATERIAL_MAP_EMISSIVE] =
+        get_gltf_uv_transform(&mat_json["emissiveTexture"]);
+    map_transforms[MESH_MATERIAL_MAP_OCCLUSION] =
+        get_gltf_uv_transform(&mat_json["occlusionTexture"]);
+
+    let emissive_strength = mat_json["extensions"]["KHR_materials_emissive_strength"]
+        ["emissiveStrength"]
+        .as_f64()
+        .unwrap_or(1.0) as f32;
+
+    let emissive = {
+        let f = mat.emissive_factor();
+        [
+            f[0] * emissive_strength,
+            f[1] * emissive_strength,
+            f[2] * emissive_strength,
+        ]
     };
 
-    let base_color_mult = mat.pbr_metallic_roughness().base_color_factor();
+    let alpha_mode = match mat.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => MeshAlphaMode::Opaque,
+        gltf::material::AlphaMode::Mask => MeshAlphaMode::Mask,
+        gltf::material::AlphaMode::Blend => MeshAlphaMode::Blend,
+    };
 
     (
-        vec![normal_map, spec_map, albedo_map],
+        vec![
+            normal_map,
+            spec_map,
+            albedo_map,
+            emissive_map,
+            occlusion_map,
+        ],
         MeshMaterial {
-            base_color_mult,
-            maps: [0, 1, 2],
+            base_color_mult: pbr.base_color_factor(),
+            maps: [0, 1, 2, 3, 4],
+            map_transforms,
             emissive,
+            metallic_mult: pbr.metallic_factor(),
+            roughness_mult: pbr.roughness_factor(),
+            normal_scale: mat.normal_texture().map(|t| t.scale()).unwrap_or(1.0),
+            occlusion_strength: mat.occlusion_texture().map(|t| t.strength()).unwrap_or(1.0),
+            alpha_mode,
+            alpha_cutoff: mat.alpha_cutoff(),
+            double_sided: mat.double_sided(),
+            specular_exponent_map: false,
         },
     )
 }
 
-#[snoozy(cache)]
-pub async fn load_gltf_scene_snoozy(
-    ctx: Context,
+fn load_gltf_mesh(
+    mesh: &gltf::Mesh,
     path: &AssetPath,
-    scale: &f32,
-) -> Result<TriangleMesh> {
-    let (gltf, buffers, _imgs) = gltf::import(path.to_path_lossy(ctx).await?)?;
+    buffers: &[gltf::buffer::Data],
+    raw_json: &serde_json::Value,
+) -> (TriangleMesh, GltfMeshDeformation) {
+    let mut res: TriangleMesh = TriangleMesh::default();
+    let mut deformation = GltfMeshDeformation::default();
 
-    if let Some(scene) = gltf.default_scene() {
-        let mut res: TriangleMesh = TriangleMesh::default();
+    // Per morph target, per vertex; flattened at the end
+    let mut morph_deltas: Vec<Vec<[f32; 6]>> = Vec::new();
 
-        let mut process_node = |node: &gltf::scene::Node, xform: Matrix4| {
-            if let Some(mesh) = node.mesh() {
-                for prim in mesh.primitives() {
-                    let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
+    let mut has_normal: Vec<bool> = Vec::new();
+    let mut has_tangent: Vec<bool> = Vec::new();
 
-                    let res_material_index = res.materials.len() as u32;
+    for prim in mesh.primitives() {
+        let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
 
-                    {
-                        let (mut maps, mut material) = load_gltf_material(&prim.material(), path);
+        // Collect positions (required)
+        let positions = if let Some(iter) = reader.read_positions() {
+            iter.collect::<Vec<_>>()
+        } else {
+            continue;
+        };
 
-                        let map_base = res.maps.len() as u32;
-                        for id in material.maps.iter_mut() {
-                            *id += map_base;
-                        }
+        // Collect normals (optional; generated later)
+        let mut normals = if let Some(iter) = reader.read_normals() {
+            has_normal.resize(has_normal.len() + positions.len(), true);
+            iter.collect::<Vec<_>>()
+        } else {
+            has_normal.resize(has_normal.len() + positions.len(), false);
+            vec![[0.0, 0.0, 0.0]; positions.len()]
+        };
 
-                        res.materials.push(material);
-                        res.maps.append(&mut maps);
-                    }
+        let res_material_index = res.materials.len() as u32;
 
-                    // Collect positions (required)
-                    let positions = if let Some(iter) = reader.read_positions() {
-                        iter.collect::<Vec<_>>()
-                    } else {
-                        return;
-                    };
-
-                    // Collect normals (required)
-                    let normals = if let Some(iter) = reader.read_normals() {
-                        iter.collect::<Vec<_>>()
-                    } else {
-                        return;
-                    };
-
-                    // Collect tangents (optional)
-                    let mut tangents = if let Some(iter) = reader.read_tangents() {
-                        iter.collect::<Vec<_>>()
-                    } else {
-                        vec![[1.0, 0.0, 0.0, 0.0]; positions.len()]
-                    };
-
-                    // Collect uvs (optional)
-                    let mut uvs = if let Some(iter) = reader.read_tex_coords(0) {
-                        iter.into_f32().collect::<Vec<_>>()
-                    } else {
-                        vec![[0.0, 0.0]; positions.len()]
-                    };
-
-                    // Collect colors (optional)
-                    let colors = if let Some(iter) = reader.read_colors(0) {
-                        iter.into_rgba_f32().collect::<Vec<_>>()
-                    } else {
-                        vec![[1.0, 1.0, 1.0, 1.0]; positions.len()]
-                    };
-
-                    // Collect material ids
-                    let mut material_ids = vec![res_material_index; positions.len()];
-
-                    // --------------------------------------------------------
-                    // Write it all to the output
-
-                    {
-                        let mut indices: Vec<u32>;
-                        let base_index = res.positions.len() as u32;
-
-                        if let Some(indices_reader) = reader.read_indices() {
-                            indices = indices_reader.into_u32().map(|i| i + base_index).collect();
-                        } else {
-                            indices = (base_index..(base_index + positions.len() as u32)).collect();
-                        }
-
-                        res.indices.append(&mut indices);
-                        res.tangents.append(&mut tangents);
-                        res.material_ids.append(&mut material_ids);
-                    }
+        {
+            let (mut maps, mut material) =
+                load_gltf_material(&prim.material(), path, buffers, raw_json);
 
-                    for p in positions {
-                        let pos =
-                            Point3::from_homogeneous(xform * Point3::from(p).to_homogeneous())
-                                .unwrap();
-                        res.positions.push([pos.x, pos.y, pos.z]);
-                    }
+            let map_base = res.maps.len() as u32;
+            for id in material.maps.iter_mut() {
+                *id += map_base;
+            }
 
-                    for n in normals {
-                        let norm =
-                            Vector3::from_homogeneous(xform * Vector3::from(n).to_homogeneous())
-                                .unwrap()
-                                .normalize();
-                        res.normals.push([norm.x, norm.y, norm.z]);
-                    }
+            res.materials.push(material);
+            res.maps.append(&mut maps);
+        }
+
+        // Collect tangents (optional; generated later)
+        let mut tangents = if let Some(iter) = reader.read_tangents() {
+            has_tangent.resize(has_tangent.len() + positions.len(), true);
+            iter.collect::<Vec<_>>()
+        } else {
+            has_tangent.resize(has_tangent.len() + positions.len(), false);
+            vec![[1.0, 0.0, 0.0, 1.0]; positions.len()]
+        };
+
+        // Collect uvs (optional)
+        let mut uvs = if let Some(iter) = reader.read_tex_coords(0) {
+            iter.into_f32().collect::<Vec<_>>()
+        } else {
+            vec![[0.0, 0.0]; positions.len()]
+        };
+
+        // Collect colors (optional)
+        let mut colors = if let Some(iter) = reader.read_colors(0) {
+            iter.into_rgba_f32().collect::<Vec<_>>()
+        } else {
+            vec![[1.0, 1.0, 1.0, 1.0]; positions.len()]
+        };
 
-                    for c in colors {
-                        res.colors.push(c);
+        // Collect material ids
+        let mut material_ids = vec![res_material_index; positions.len()];
+
+        // Collect skinning attributes (optional)
+        {
+            let base_vertex = res.positions.len();
+
+            if let (Some(joints), Some(weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                // Vertices of previous primitives without skinning data are left in the rest pose
+                deformation.joints.resize(base_vertex, [0; 4]);
+                deformation.weights.resize(base_vertex, [0.0; 4]);
+
+                deformation.joints.extend(
+                    joints
+                        .into_u16()
+                        .map(|j| [j[0] as u32, j[1] as u32, j[2] as u32, j[3] as u32]),
+                );
+                deformation.weights.extend(weights.into_f32());
+            }
+
+            for (target_idx, (target_positions, target_normals, _)) in
+                reader.read_morph_targets().enumerate()
+            {
+                if morph_deltas.len() <= target_idx {
+                    morph_deltas.push(Vec::new());
+                }
+
+                let deltas = &mut morph_deltas[target_idx];
+                deltas.resize(base_vertex, [0.0; 6]);
+                deltas.resize(base_vertex + positions.len(), [0.0; 6]);
+
+                if let Some(target_positions) = target_positions {
+                    for (d, p) in deltas[base_vertex..].iter_mut().zip(target_positions) {
+                        d[0..3].copy_from_slice(&p);
                     }
+                }
 
-                    res.uvs.append(&mut uvs);
+                if let Some(target_normals) = target_normals {
+                    for (d, n) in deltas[base_vertex..].iter_mut().zip(target_normals) {
+                        d[3..6].copy_from_slice(&n);
+                    }
                 }
             }
+        }
+
+        // --------------------------------------------------------
+        // Write it all to the output
+
+        {
+            let mut indices: Vec<u32>;
+            let base_index = res.positions.len() as u32;
+
+            if let Some(indices_reader) = reader.read_indices() {
+                indices = indices_reader.into_u32().map(|i| i + base_index).collect();
+            } else {
+                indices = (base_index..(base_index + positions.len() as u32)).collect();
+            }
+
+            res.indices.append(&mut indices);
+            res.tangents.append(&mut tangents);
+            res.material_ids.append(&mut material_ids);
+        }
+
+        res.positions.extend(positions);
+        res.normals.append(&mut normals);
+        res.colors.append(&mut colors);
+        res.uvs.append(&mut uvs);
+    }
+
+    let vertex_count = res.positions.len();
+
+    if !deformation.joints.is_empty() {
+        deformation.joints.resize(vertex_count, [0; 4]);
+        deformation.weights.resize(vertex_count, [0.0; 4]);
+    }
+
+    for deltas in morph_deltas.iter_mut() {
+        deltas.resize(vertex_count, [0.0; 6]);
+    }
+
+    // The glTF spec mandates flat normals when they're missing. That can duplicate vertices,
+    // so the deformation data needs to follow suit.
+    if has_normal.iter().any(|n| !n) {
+        let remap = res.generate_normals(NormalGeneration::Flat, &has_normal);
+
+        if remap.len() != vertex_count {
+            if !deformation.joints.is_empty() {
+                deformation.joints = remap_vertex_data(&deformation.joints, &remap);
+                deformation.weights = remap_vertex_data(&deformation.weights, &remap);
+            }
+
+            for deltas in morph_deltas.iter_mut() {
+                *deltas = remap_vertex_data(deltas, &remap);
+            }
+
+            has_tangent = remap_vertex_data(&has_tangent, &remap);
+        }
+    }
+
+    if has_tangent.iter().any(|t| !t) {
+        res.generate_tangents(&has_tangent);
+    }
+
+    deformation.morph_target_count = morph_deltas.len() as u32;
+    for mut deltas in morph_deltas.into_iter() {
+        deformation.morph_deltas.append(&mut deltas);
+    }
+
+    (res, deformation)
+}
+
+impl TriangleMesh {
+    // Appends a material with placeholder maps which only uses vertex colors. Returns its index.
+    pub fn add_default_material(&mut self) -> u32 {
+        let map_base = self.maps.len() as u32;
+
+        let mut maps = [0u32; MESH_MATERIAL_MAP_COUNT];
+        for (i, id) in maps.iter_mut().enumerate() {
+            *id = map_base + i as u32;
+        }
+
+        let mut placeholders =
+            vec![MeshMaterialMap::Placeholder([255, 255, 255, 255]); MESH_MATERIAL_MAP_COUNT];
+        placeholders[MESH_MATERIAL_MAP_NORMAL] = MeshMaterialMap::Placeholder([127, 127, 255, 255]);
+        self.maps.append(&mut placeholders);
+
+        self.materials.push(MeshMaterial {
+            base_color_mult: [1.0, 1.0, 1.0, 1.0],
+            maps,
+            map_transforms: [IDENTITY_UV_TRANSFORM; MESH_MATERIAL_MAP_COUNT],
+            emissive: [0.0, 0.0, 0.0],
+            metallic_mult: 0.0,
+            roughness_mult: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_mode: MeshAlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            specular_exponent_map: false,
+        });
+
+        self.materials.len() as u32 - 1
+    }
+
+    // Appends `other` with its vertices transformed by `xform`.
+    // Materials and maps are appended as well, and the ids remapped accordingly.
+    // Both meshes must have the same topology, unless `self` is empty.
+    pub fn append_transformed(&mut self, other: &TriangleMesh, xform: &Matrix4) {
+        if self.indices.is_empty() {
+            self.topology = other.topology;
+        }
+        debug_assert!(self.topology == other.topology || other.indices.is_empty());
+
+        let base_index = self.positions.len() as u32;
+        let material_base = self.materials.len() as u32;
+        let map_base = self.maps.len() as u32;
+
+        let linear_xform: Matrix3 = xform.fixed_slice::<na::U3, na::U3>(0, 0).into_owned();
+        let normal_xform = linear_xform
+            .try_inverse()
+            .unwrap_or_else(Matrix3::identity)
+            .transpose();
+
+        // Mirroring transforms flip the handedness of the tangent frame
+        let handedness = if linear_xform.determinant() < 0.0 {
+            -1.0
+        } else {
+            1.0
         };
 
-        let xform = Matrix4::new_scaling(*scale);
-        for node in scene.nodes() {
-            iter_gltf_node_tree(&node, xform, &mut process_node);
+        for p in other.positions.iter() {
+            let pos = Point3::from_homogeneous(xform * Point3::from(*p).to_homogeneous()).unwrap();
+            self.positions.push([pos.x, pos.y, pos.z]);
+        }
+
+        for n in other.normals.iter() {
+            let norm = (normal_xform * Vector3::from(*n)).normalize();
+            self.normals.push([norm.x, norm.y, norm.z]);
+        }
+
+        for t in other.tangents.iter() {
+            let tangent = (linear_xform * Vector3::new(t[0], t[1], t[2])).normalize();
+            self.tangents
+                .push([tangent.x, tangent.y, tangent.z, t[3] * handedness]);
+        }
+
+        self.colors.extend_from_slice(&other.colors);
+        self.uvs.extend_from_slice(&other.uvs);
+        self.indices
+            .extend(other.indices.iter().map(|i| i + base_index));
+        self.material_ids
+            .extend(other.material_ids.iter().map(|i| i + material_base));
+
+        for material in other.materials.iter() {
+            let mut material = material.clone();
+            for id in material.maps.iter_mut() {
+                *id += map_base;
+            }
+            self.materials.push(material);
+        }
+
+        self.maps.extend_from_slice(&other.maps);
+    }
+}
+
+#[derive(Abomonation, Clone)]
+pub struct GltfSceneNode {
+    pub name: Option<String>,
+    // Rest pose local transform. The rotation is an `[x, y, z, w]` quaternion.
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub mesh: Option<u32>,
+    pub skin: Option<u32>,
+    pub camera: Option<u32>,
+    // Index into `GltfScene::lights`
+    pub light: Option<u32>,
+    // Rest pose morph target weights, taken from the node or its mesh
+    pub morph_weights: Vec<f32>,
+    pub children: Vec<u32>,
+}
+
+impl GltfSceneNode {
+    pub fn rest_pose(&self) -> GltfNodePose {
+        let [x, y, z, w] = self.rotation;
+
+        GltfNodePose {
+            translation: Vector3::from(self.translation),
+            rotation: UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+            scale: Vector3::from(self.scale),
+            morph_weights: self.morph_weights.clone(),
+        }
+    }
+
+    pub fn local_transform(&self) -> Matrix4 {
+        self.rest_pose().local_transform()
+    }
+}
+
+#[derive(Abomonation, Clone)]
+pub struct GltfSkin {
+    // Nodes acting as joints, in the order referenced by the `JOINTS_0` vertex attribute
+    pub joints: Vec<u32>,
+    // Column-major, one per joint
+    pub inverse_bind_matrices: Vec<[f32; 16]>,
+}
+
+// Per-vertex data for deforming a mesh of a `GltfScene`. Vertex order matches the `TriangleMesh`.
+#[derive(Abomonation, Clone, Default)]
+pub struct GltfMeshDeformation {
+    // Empty if the mesh is not skinned
+    pub joints: Vec<[u32; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    pub morph_target_count: u32,
+    // Position delta followed by normal delta, per morph target, per vertex
+    pub morph_deltas: Vec<[f32; 6]>,
+}
+
+impl GltfMeshDeformation {
+    pub fn is_skinned(&self) -> bool {
+        !self.joints.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.is_skinned() && 0 == self.morph_target_count
+    }
+}
+
+#[derive(Abomonation, Clone)]
+pub enum GltfProjection {
+    Perspective {
+        // Vertical, in radians
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        // Infinite if not specified
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Abomonation, Clone)]
+pub struct GltfCamera {
+    pub name: Option<String>,
+    pub projection: GltfProjection,
+}
+
+impl GltfCamera {
+    // Creates a `FirstPersonCamera` placed at the world-space transform of the camera's node.
+    pub fn to_first_person_camera(&self, world_xform: &Matrix4) -> FirstPersonCamera {
+        let position = Point3::from_homogeneous(world_xform * Point3::origin().to_homogeneous())
+            .unwrap_or_else(Point3::origin);
+
+        // Strip any scaling before extracting the orientation
+        let mut rotation: Matrix3 = world_xform.fixed_slice::<na::U3, na::U3>(0, 0).into_owned();
+        for mut column in rotation.column_iter_mut() {
+            column.normalize_mut();
+        }
+        let rotation =
+            UnitQuaternion::from_rotation_matrix(&na::Rotation3::from_matrix_unchecked(rotation));
+
+        let mut camera = FirstPersonCamera::new(position);
+        camera.set_orientation(rotation);
+
+        camera.projection = self.to_camera_projection();
+        camera
+    }
+
+    // Perspective cameras without an aspect ratio keep the default one,
+    // and are meant to have it overridden to match the viewport.
+    pub fn to_camera_projection(&self) -> CameraProjection {
+        match self.projection {
+            GltfProjection::Perspective {
+                yfov,
+                aspect_ratio,
+                znear,
+                zfar,
+            } => CameraProjection::Perspective {
+                fov_y: yfov.to_degrees(),
+                aspect: aspect_ratio.unwrap_or(1.6),
+                near: znear,
+                far: zfar,
+            },
+            GltfProjection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => CameraProjection::Orthographic {
+                left: -xmag,
+                right: xmag,
+                bottom: -ymag,
+                top: ymag,
+                near: znear,
+                far: zfar,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Abomonation)]
+pub enum GltfLightType {
+    Directional,
+    Point,
+    Spot,
+}
+
+// A light from the KHR_lights_punctual extension. Lights shine along their node's -Z axis.
+#[derive(Abomonation, Clone)]
+pub struct GltfLight {
+    pub name: Option<String>,
+    pub light_type: GltfLightType,
+    pub color: [f32; 3],
+    // Candela for point and spot lights, lux for directional ones
+    pub intensity: f32,
+    // Infinite if not specified
+    pub range: Option<f32>,
+    // Radians; only used by spot lights
+    pub inner_cone_angle: f32,
+    pub outer_cone_angle: f32,
+}
+
+// Changes applied on top of a `GltfScene` when drawing it. Nodes are matched by name.
+#[derive(Serialize, Clone, Default)]
+pub struct GltfSceneOverrides {
+    // Replaces the local transform of a node
+    pub node_transforms: Vec<(String, Matrix4)>,
+    // Hides a node along with all of its children
+    pub hidden_nodes: Vec<String>,
+}
+
+impl GltfSceneOverrides {
+    pub fn with_node_transform(mut self, name: &str, xform: Matrix4) -> Self {
+        self.node_transforms.push((name.to_string(), xform));
+        self
+    }
+
+    pub fn with_hidden_node(mut self, name: &str) -> Self {
+        self.hidden_nodes.push(name.to_string());
+        self
+    }
+}
+
+#[derive(Abomonation, Clone, Default)]
+pub struct GltfScene {
+    pub meshes: Vec<TriangleMesh>,
+    // Parallel to `meshes`
+    pub deformations: Vec<GltfMeshDeformation>,
+    pub nodes: Vec<GltfSceneNode>,
+    pub root_nodes: Vec<u32>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<GltfAnimationClip>,
+    pub cameras: Vec<GltfCamera>,
+    pub lights: Vec<GltfLight>,
+    pub scale: f32,
+}
+
+impl GltfScene {
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|n| n.name.as_ref().map(String::as_str) == Some(name))
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations
+            .iter()
+            .position(|a| a.name.as_ref().map(String::as_str) == Some(name))
+    }
+
+    pub fn rest_pose(&self) -> GltfScenePose {
+        GltfScenePose {
+            nodes: self.nodes.iter().map(GltfSceneNode::rest_pose).collect(),
+        }
+    }
+
+    // Evaluates the animation clip at time `t`. Nodes it doesn't animate stay in their rest pose.
+    pub fn sample_pose(&self, clip: usize, t: f32) -> GltfScenePose {
+        let mut pose = self.rest_pose();
+        if let Some(clip) = self.animations.get(clip) {
+            clip.apply(t, &mut pose);
+        }
+        pose
+    }
+
+    // World-space transforms of all nodes reachable from the scene roots, in depth-first order.
+    // The flag is false for hidden nodes and their children.
+    pub fn node_world_transforms(
+        &self,
+        pose: &GltfScenePose,
+        overrides: &GltfSceneOverrides,
+    ) -> Vec<(usize, Matrix4, bool)> {
+        let mut res = Vec::new();
+        let mut stack: Vec<(u32, Matrix4, bool)> = self
+            .root_nodes
+            .iter()
+            .rev()
+            .map(|node| (*node, Matrix4::new_scaling(self.scale), true))
+            .collect();
+
+        while let Some((node_idx, parent_xform, parent_visible)) = stack.pop() {
+            let node = &self.nodes[node_idx as usize];
+            let name = node.name.as_ref().map(String::as_str);
+
+            let visible = parent_visible
+                && !overrides
+                    .hidden_nodes
+                    .iter()
+                    .any(|hidden| Some(hidden.as_str()) == name);
+
+            let local_xform = overrides
+                .node_transforms
+                .iter()
+                .find(|(n, _)| Some(n.as_str()) == name)
+                .map(|(_, xform)| *xform)
+                .unwrap_or_else(|| pose.nodes[node_idx as usize].local_transform());
+
+            let xform = parent_xform * local_xform;
+            res.push((node_idx as usize, xform, visible));
+
+            for child in node.children.iter().rev() {
+                stack.push((*child, xform, visible));
+            }
+        }
+
+        res
+    }
+
+    // World-space transforms of the visible rest pose nodes for which `get_item` returns an index
+    fn node_item_instances(
+        &self,
+        overrides: &GltfSceneOverrides,
+        get_item: impl Fn(&GltfSceneNode) -> Option<u32>,
+    ) -> Vec<(usize, Matrix4)> {
+        self.node_world_transforms(&self.rest_pose(), overrides)
+            .into_iter()
+            .filter(|(_, _, visible)| *visible)
+            .filter_map(|(node, xform, _)| {
+                get_item(&self.nodes[node]).map(|item| (item as usize, xform))
+            })
+            .collect()
+    }
+
+    // World-space transforms of all the visible nodes which reference a mesh
+    pub fn mesh_instances(&self, overrides: &GltfSceneOverrides) -> Vec<(usize, Matrix4)> {
+        self.node_item_instances(overrides, |node| node.mesh)
+    }
+
+    // World-space transforms of all the visible nodes which reference a camera
+    pub fn camera_instances(&self, overrides: &GltfSceneOverrides) -> Vec<(usize, Matrix4)> {
+        self.node_item_instances(overrides, |node| node.camera)
+    }
+
+    // World-space transforms of all the visible nodes which reference a light
+    pub fn light_instances(&self, overrides: &GltfSceneOverrides) -> Vec<(usize, Matrix4)> {
+        self.node_item_instances(overrides, |node| node.light)
+    }
+
+    // A `FirstPersonCamera` placed at the first visible node referencing the given camera
+    pub fn first_person_camera(&self, camera: usize) -> Option<FirstPersonCamera> {
+        self.camera_instances(&Default::default())
+            .into_iter()
+            .find(|(c, _)| *c == camera)
+            .map(|(c, xform)| self.cameras[c].to_first_person_camera(&xform))
+    }
+
+    // World-space joint matrices of a skin, given the output of `node_world_transforms`
+    pub fn skin_joint_matrices(
+        &self,
+        skin: usize,
+        node_world_transforms: &[(usize, Matrix4, bool)],
+    ) -> Vec<Matrix4> {
+        let mut world = vec![Matrix4::identity(); self.nodes.len()];
+        for (node, xform, _) in node_world_transforms.iter() {
+            world[*node] = *xform;
         }
 
-        Ok(res)
+        let skin = &self.skins[skin];
+        skin.joints
+            .iter()
+            .zip(skin.inverse_bind_matrices.iter())
+            .map(|(joint, ibm)| world[*joint as usize] * Matrix4::from_column_slice(ibm))
+            .collect()
+    }
+
+    pub fn flatten(&self) -> TriangleMesh {
+        let mut res = TriangleMesh::default();
+        for (mesh, xform) in self.mesh_instances(&Default::default()) {
+            res.append_transformed(&self.meshes[mesh], &xform);
+        }
+        res
+    }
+}
+
+fn load_gltf_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> GltfSkin {
+    let joints: Vec<u32> = skin.joints().map(|joint| joint.index() as u32).collect();
+
+    let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+    let mut inverse_bind_matrices: Vec<[f32; 16]> = reader
+        .read_inverse_bind_matrices()
+        .map(|iter| {
+            iter.map(|m| {
+                let m: Matrix4 = m.into();
+                let mut res = [0.0f32; 16];
+                res.copy_from_slice(m.as_slice());
+                res
+            })
+            .collect()
+        })
+        .unwrap_or_default();
+
+    // Missing inverse bind matrices are identity, as per the spec
+    let mut identity = [0.0f32; 16];
+    identity.copy_from_slice(Matrix4::identity().as_slice());
+    inverse_bind_matrices.resize(joints.len(), identity);
+
+    GltfSkin {
+        joints,
+        inverse_bind_matrices,
+    }
+}
+
+fn load_gltf_animation(
+    anim: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+) -> GltfAnimationClip {
+    use gltf::animation::{util::ReadOutputs, Interpolation};
+
+    let mut duration = 0.0f32;
+
+    let channels = anim
+        .channels()
+        .filter_map(|channel| {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times: Vec<f32> = reader.read_inputs()?.collect();
+
+            let (property, values): (_, Vec<f32>) = match reader.read_outputs()? {
+                ReadOutputs::Translations(iter) => (
+                    GltfAnimationProperty::Translation,
+                    iter.flat_map(|v| v.to_vec()).collect(),
+                ),
+                ReadOutputs::Rotations(iter) => (
+                    GltfAnimationProperty::Rotation,
+                    iter.into_f32().flat_map(|v| v.to_vec()).collect(),
+                ),
+                ReadOutputs::Scales(iter) => (
+                    GltfAnimationProperty::Scale,
+                    iter.flat_map(|v| v.to_vec()).collect(),
+                ),
+                ReadOutputs::MorphTargetWeights(iter) => (
+                    GltfAnimationProperty::MorphWeights,
+                    iter.into_f32().collect(),
+                ),
+            };
+
+            let interpolation = match channel.sampler().interpolation() {
+                Interpolation::Step => GltfAnimationInterpolation::Step,
+                Interpolation::Linear => GltfAnimationInterpolation::Linear,
+                Interpolation::CubicSpline => GltfAnimationInterpolation::CubicSpline,
+            };
+
+            if let Some(last) = times.last() {
+                duration = duration.max(*last);
+            }
+
+            Some(GltfAnimationChannel {
+                node: channel.target().node().index() as u32,
+                property,
+                interpolation,
+                times,
+                values,
+            })
+        })
+        .collect();
+
+    GltfAnimationClip {
+        name: anim.name().map(str::to_string),
+        channels,
+        duration,
+    }
+}
+
+fn load_gltf_camera(camera: &gltf::Camera) -> GltfCamera {
+    use gltf::camera::Projection;
+
+    let projection = match camera.projection() {
+        Projection::Perspective(p) => GltfProjection::Perspective {
+            yfov: p.yfov(),
+            aspect_ratio: p.aspect_ratio(),
+            znear: p.znear(),
+            zfar: p.zfar(),
+        },
+        Projection::Orthographic(o) => GltfProjection::Orthographic {
+            xmag: o.xmag(),
+            ymag: o.ymag(),
+            znear: o.znear(),
+            zfar: o.zfar(),
+        },
+    };
+
+    GltfCamera {
+        name: camera.name().map(str::to_string),
+        projection,
+    }
+}
+
+// KHR_lights_punctual is read from the raw JSON, so it doesn't depend on gltf crate features
+fn load_gltf_lights(raw_json: &serde_json::Value) -> Vec<GltfLight> {
+    let lights =
+        if let Some(lights) = raw_json["extensions"]["KHR_lights_punctual"]["lights"].as_array() {
+            lights
+        } else {
+            return Vec::new();
+        };
+
+    lights
+        .iter()
+        .map(|light| {
+            let light_type = match light["type"].as_str() {
+                Some("directional") => GltfLightType::Directional,
+                Some("spot") => GltfLightType::Spot,
+                _ => GltfLightType::Point,
+            };
+
+            let mut color = [1.0f32; 3];
+            if let Some(c) = light["color"].as_array() {
+                for (dst, src) in color.iter_mut().zip(c.iter()) {
+                    *dst = src.as_f64().unwrap_or(1.0) as f32;
+                }
+            }
+
+            let spot = &light["spot"];
+
+            GltfLight {
+                name: light["name"].as_str().map(str::to_string),
+                light_type,
+                color,
+                intensity: light["intensity"].as_f64().unwrap_or(1.0) as f32,
+                range: light["range"].as_f64().map(|r| r as f32),
+                inner_cone_angle: spot["innerConeAngle"].as_f64().unwrap_or(0.0) as f32,
+                outer_cone_angle: spot["outerConeAngle"]
+                    .as_f64()
+                    .unwrap_or(std::f64::consts::FRAC_PI_4)
+                    as f32,
+            }
+        })
+        .collect()
+}
+
+#[snoozy(cache)]
+pub async fn load_gltf_scene_hierarchy_snoozy(
+    ctx: Context,
+    path: &AssetPath,
+    scale: &f32,
+) -> Result<GltfScene> {
+    let file_path = path.to_path_lossy(ctx).await?;
+    let (gltf, buffers, _imgs) = gltf::import(&file_path)?;
+    let raw_json = load_gltf_raw_json(&file_path)?;
+
+    if let Some(scene) = gltf.default_scene() {
+        let (meshes, deformations): (Vec<_>, Vec<_>) = gltf
+            .meshes()
+            .map(|mesh| load_gltf_mesh(&mesh, path, &buffers, &raw_json))
+            .unzip();
+
+        let nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+
+                let morph_weights = node
+                    .weights()
+                    .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+                    .map(|weights| weights.to_vec())
+                    .unwrap_or_default();
+
+                GltfSceneNode {
+                    name: node.name().map(str::to_string),
+                    translation,
+                    rotation,
+                    scale,
+                    mesh: node.mesh().map(|mesh| mesh.index() as u32),
+                    skin: node.skin().map(|skin| skin.index() as u32),
+                    camera: node.camera().map(|camera| camera.index() as u32),
+                    light: raw_json["nodes"][node.index()]["extensions"]["KHR_lights_punctual"]
+                        ["light"]
+                        .as_u64()
+                        .map(|light| light as u32),
+                    morph_weights,
+                    children: node.children().map(|child| child.index() as u32).collect(),
+                }
+            })
+            .collect();
+
+        Ok(GltfScene {
+            meshes,
+            deformations,
+            nodes,
+            root_nodes: scene.nodes().map(|node| node.index() as u32).collect(),
+            skins: gltf
+                .skins()
+                .map(|skin| load_gltf_skin(&skin, &buffers))
+                .collect(),
+            animations: gltf
+                .animations()
+                .map(|anim| load_gltf_animation(&anim, &buffers))
+                .collect(),
+            cameras: gltf
+                .cameras()
+                .map(|camera| load_gltf_camera(&camera))
+                .collect(),
+            lights: load_gltf_lights(&raw_json),
+            scale: *scale,
+        })
     } else {
         Err(format_err!("No default scene found in gltf"))
     }
 }
 
+#[snoozy(cache)]
+pub async fn load_gltf_scene_snoozy(
+    mut ctx: Context,
+    path: &AssetPath,
+    scale: &f32,
+) -> Result<TriangleMesh> {
+    let scene = ctx
+        .get(load_gltf_scene_hierarchy(path.clone(), *scale))
+        .await?;
+    Ok(scene.flatten())
+}
+
+#[snoozy]
+pub async fn gltf_scene_mesh_snoozy(
+    mut ctx: Context,
+    scene: &SnoozyRef<GltfScene>,
+    mesh_index: &usize,
+) -> Result<TriangleMesh> {
+    let scene = ctx.get(scene).await?;
+    scene
+        .meshes
+        .get(*mesh_index)
+        .cloned()
+        .ok_or_else(|| format_err!("Mesh index {} out of range", *mesh_index))
+}
+
+#[snoozy]
+pub async fn gltf_scene_mesh_deformation_snoozy(
+    mut ctx: Context,
+    scene: &SnoozyRef<GltfScene>,
+    mesh_index: &usize,
+) -> Result<GltfMeshDeformation> {
+    let scene = ctx.get(scene).await?;
+    scene
+        .deformations
+        .get(*mesh_index)
+        .cloned()
+        .ok_or_else(|| format_err!("Mesh index {} out of range", *mesh_index))
+}
+
 #[derive(Clone, Copy, Abomonation)]
 #[repr(C)]
 pub struct RasterGpuVertex {
@@ -253,23 +1182,21 @@ fn pack_unit_direction_11_10_11(x: f32, y: f32, z: f32) -> u32 {
 
 #[derive(Clone, Abomonation)]
 pub struct RasterGpuMesh {
-    verts: Vec<RasterGpuVertex>,
-    uvs: Vec<[f32; 2]>,
-    tangents: Vec<[f32; 4]>,
-    colors: Vec<[f32; 4]>,
-    indices: Vec<u32>,
-    material_ids: Vec<u32>,
-    materials: Vec<MeshMaterial>,
-    maps: Vec<MeshMaterialMap>,
+    pub(crate) verts: Vec<RasterGpuVertex>,
+    pub(crate) uvs: Vec<[f32; 2]>,
+    pub(crate) tangents: Vec<[f32; 4]>,
+    pub(crate) colors: Vec<[f32; 4]>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) material_ids: Vec<u32>,
+    pub(crate) materials: Vec<MeshMaterial>,
+    pub(crate) maps: Vec<MeshMaterialMap>,
+    pub(crate) meshlets: MeshletData,
+    pub aabb: Aabb,
+    // Bounds of the vertices referenced by each material's triangles, indexed by material id
+    pub material_aabbs: Vec<Aabb>,
 }
 
-#[snoozy]
-pub async fn make_raster_mesh_snoozy(
-    mut ctx: Context,
-    mesh: &SnoozyRef<TriangleMesh>,
-) -> Result<RasterGpuMesh> {
-    let mesh = ctx.get(mesh).await?;
-
+pub(crate) fn build_raster_mesh(mesh: &TriangleMesh) -> Result<RasterGpuMesh> {
     let mut verts: Vec<RasterGpuVertex> = Vec::with_capacity(mesh.positions.len());
 
     for (i, pos) in mesh.positions.iter().enumerate() {
@@ -281,6 +1208,20 @@ pub async fn make_raster_mesh_snoozy(
         });
     }
 
+    let mut aabb = Aabb::empty();
+    let material_count = mesh
+        .material_ids
+        .iter()
+        .max()
+        .map_or(0, |m| *m as usize + 1);
+    let mut material_aabbs = vec![Aabb::empty(); material_count];
+
+    for i in mesh.indices.iter() {
+        let pos = &mesh.positions[*i as usize];
+        aabb.grow(pos);
+        material_aabbs[mesh.material_ids[*i as usize] as usize].grow(pos);
+    }
+
     Ok(RasterGpuMesh {
         verts,
         uvs: mesh.uvs.clone(),
@@ -290,21 +1231,70 @@ pub async fn make_raster_mesh_snoozy(
         material_ids: mesh.material_ids.clone(),
         materials: mesh.materials.clone(),
         maps: mesh.maps.clone(),
+        meshlets: mesh.build_meshlets()?,
+        aabb,
+        material_aabbs,
     })
 }
 
+#[snoozy]
+pub async fn make_raster_mesh_snoozy(
+    mut ctx: Context,
+    mesh: &SnoozyRef<TriangleMesh>,
+) -> Result<RasterGpuMesh> {
+    let mesh = ctx.get(mesh).await?;
+    build_raster_mesh(&mesh)
+}
+
+const GPU_MATERIAL_FLAG_ALPHA_MASK: u32 = 1;
+const GPU_MATERIAL_FLAG_ALPHA_BLEND: u32 = 2;
+const GPU_MATERIAL_FLAG_DOUBLE_SIDED: u32 = 4;
+const GPU_MATERIAL_FLAG_SPECULAR_EXPONENT_MAP: u32 = 8;
+
+// Must match `MeshMaterial` in `assets/shaders/mesh_material.inc`.
+// Everything past `base_color_mult` is a scalar, so the std430 layout is the same as `repr(C)`.
 #[derive(Copy, Clone, Abomonation, Serialize)]
 #[repr(C)]
 struct GpuMaterial {
     base_color_mult: [f32; 4],
-    maps: [u32; 4],
+    maps: [u32; MESH_MATERIAL_MAP_COUNT],
+    emissive: [f32; 3],
+    metallic_mult: f32,
+    roughness_mult: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    flags: u32,
+    map_transforms: [[f32; 6]; MESH_MATERIAL_MAP_COUNT],
 }
 
-impl Default for GpuMaterial {
-    fn default() -> Self {
+impl From<&MeshMaterial> for GpuMaterial {
+    fn from(m: &MeshMaterial) -> Self {
+        let mut flags = match m.alpha_mode {
+            MeshAlphaMode::Opaque => 0,
+            MeshAlphaMode::Mask => GPU_MATERIAL_FLAG_ALPHA_MASK,
+            MeshAlphaMode::Blend => GPU_MATERIAL_FLAG_ALPHA_BLEND,
+        };
+
+        if m.double_sided {
+            flags |= GPU_MATERIAL_FLAG_DOUBLE_SIDED;
+        }
+
+        if m.specular_exponent_map {
+            flags |= GPU_MATERIAL_FLAG_SPECULAR_EXPONENT_MAP;
+        }
+
         Self {
-            base_color_mult: [0.0f32; 4],
-            maps: [0; 4],
+            base_color_mult: m.base_color_mult,
+            maps: [0; MESH_MATERIAL_MAP_COUNT],
+            emissive: m.emissive,
+            metallic_mult: m.metallic_mult,
+            roughness_mult: m.roughness_mult,
+            normal_scale: m.normal_scale,
+            occlusion_strength: m.occlusion_strength,
+            alpha_cutoff: m.alpha_cutoff,
+            flags,
+            map_transforms: m.map_transforms,
         }
     }
 }
@@ -315,6 +1305,10 @@ async fn upload_material_map(mut ctx: Context, map: MeshMaterialMap) -> u32 {
             ref path,
             ref params,
         } => load_tex_with_params(path.clone(), params.clone()),
+        MeshMaterialMap::InMemory {
+            ref image,
+            ref params,
+        } => load_tex_from_memory(image.clone(), params.clone()),
         MeshMaterialMap::Placeholder(ref texel_value) => make_placeholder_rgba8_tex(*texel_value),
     };
 
@@ -327,32 +1321,97 @@ async fn upload_material_map(mut ctx: Context, map: MeshMaterialMap) -> u32 {
 
 #[snoozy]
 pub async fn upload_raster_mesh_snoozy(
+    ctx: Context,
+    mesh: &SnoozyRef<RasterGpuMesh>,
+) -> Result<ShaderUniformBundle> {
+    upload_raster_mesh_impl(ctx, mesh, None).await
+}
+
+// Same as `upload_raster_mesh`, but `mesh_vertex_buf` is replaced with `vertex_buf`,
+// which must use the `RasterGpuVertex` layout, e.g. the output of `deform_raster_mesh`.
+#[snoozy]
+pub async fn upload_deformed_raster_mesh_snoozy(
+    ctx: Context,
+    mesh: &SnoozyRef<RasterGpuMesh>,
+    vertex_buf: &SnoozyRef<Buffer>,
+) -> Result<ShaderUniformBundle> {
+    upload_raster_mesh_impl(ctx, mesh, Some(vertex_buf.clone())).await
+}
+
+async fn upload_raster_mesh_impl(
     mut ctx: Context,
     mesh: &SnoozyRef<RasterGpuMesh>,
+    vertex_buf: Option<SnoozyRef<Buffer>>,
 ) -> Result<ShaderUniformBundle> {
     let mesh = ctx.get(mesh).await?;
 
-    let verts = ArcView::new(&mesh, |m| &m.verts);
+    let vertex_buf =
+        vertex_buf.unwrap_or_else(|| upload_array_buffer(ArcView::new(&mesh, |m| &m.verts)));
     let uvs = ArcView::new(&mesh, |m| &m.uvs);
     let colors = ArcView::new(&mesh, |m| &m.colors);
     let tangents = ArcView::new(&mesh, |m| &m.tangents);
     let indices = ArcView::new(&mesh, |m| &m.indices);
     let material_ids = ArcView::new(&mesh, |m| &m.material_ids);
 
-    // TODO: don't clone all the things
-    let (mesh_materials, mesh_maps) = {
-        let mesh_clone = (*mesh).clone();
-        (mesh_clone.materials, mesh_clone.maps)
+    // Vulkan doesn't allow empty buffers, so pad them for meshes without meshlets
+    let meshlet_count = mesh.meshlets.meshlets.len() as u32;
+    let (meshlet_buf, meshlet_vertex_buf, meshlet_triangle_buf) = if meshlet_count > 0 {
+        (
+            upload_array_buffer(ArcView::new(&mesh, |m| &m.meshlets.meshlets)),
+            upload_array_buffer(ArcView::new(&mesh, |m| &m.meshlets.vertices)),
+            upload_array_buffer(ArcView::new(&mesh, |m| &m.meshlets.triangles)),
+        )
+    } else {
+        (
+            upload_array_buffer(Box::new(vec![GpuMeshlet {
+                center: [0.0; 3],
+                radius: 0.0,
+                cone_apex: [0.0; 3],
+                cone_cutoff: 1.0,
+                cone_axis: [0.0; 3],
+                vertex_offset: 0,
+                vertex_count: 0,
+                triangle_offset: 0,
+                triangle_count: 0,
+            }])),
+            upload_array_buffer(Box::new(vec![0u32])),
+            upload_array_buffer(Box::new(vec![0u32])),
+        )
     };
 
-    let materials: Vec<GpuMaterial> = try_join_all(mesh_materials.into_iter().map(|m| {
-        // TODO: don't clone all the things
+    let materials_buf = upload_mesh_materials(ctx.clone(), &mesh.materials, &mesh.maps).await;
+
+    Ok(shader_uniforms!(
+        mesh_vertex_buf: vertex_buf,
+        mesh_uv_buf: upload_array_buffer(uvs),
+        mesh_color_buf: upload_array_buffer(colors),
+        mesh_tangent_buf: upload_array_buffer(tangents),
+        mesh_index_count: indices.len() as u32,
+        mesh_index_buf: upload_array_buffer(indices),
+        mesh_material_id_buf: upload_array_buffer(material_ids),
+        mesh_materials_buf: materials_buf,
+        mesh_meshlet_count: meshlet_count,
+        mesh_meshlet_buf: meshlet_buf,
+        mesh_meshlet_vertex_buf: meshlet_vertex_buf,
+        mesh_meshlet_triangle_buf: meshlet_triangle_buf,
+    ))
+}
+
+// Resolves the maps of `materials` to bindless texture indices, and uploads them
+// in the layout expected by `mesh_materials_buf`.
+pub(crate) async fn upload_mesh_materials(
+    ctx: Context,
+    materials: &[MeshMaterial],
+    maps: &[MeshMaterialMap],
+) -> SnoozyRef<Buffer> {
+    let mesh_maps = std::sync::Arc::new(maps.to_vec());
+
+    let materials: Vec<GpuMaterial> = try_join_all(materials.iter().cloned().map(|m| {
         let mesh_maps = mesh_maps.clone();
 
         let ctx = ctx.clone();
         tokio::task::spawn(async move {
-            let mut res = GpuMaterial::default();
-            res.base_color_mult = m.base_color_mult;
+            let mut res = GpuMaterial::from(&m);
 
             let maps: Vec<_> = try_join_all(m.maps.iter().map(move |map_id| {
                 let ctx = ctx.clone();
@@ -374,16 +1433,7 @@ pub async fn upload_raster_mesh_snoozy(
     .await
     .expect("tokio join error");
 
-    Ok(shader_uniforms!(
-        mesh_vertex_buf: upload_array_buffer(verts),
-        mesh_uv_buf: upload_array_buffer(uvs),
-        mesh_color_buf: upload_array_buffer(colors),
-        mesh_tangent_buf: upload_array_buffer(tangents),
-        mesh_index_count: indices.len() as u32,
-        mesh_index_buf: upload_array_buffer(indices),
-        mesh_material_id_buf: upload_array_buffer(material_ids),
-        mesh_materials_buf: upload_array_buffer(Box::new(materials)),
-    ))
+    upload_array_buffer(Box::new(materials))
 }
 
 pub fn raster_mesh_transform(offset: Vector3, rotation: UnitQuaternion) -> SnoozyRef<Buffer> {
@@ -408,3 +1458,258 @@ pub fn upload_raster_scene(
         })
         .collect()
 }
+
+// Draws every visible mesh node of the scene with its own world transform.
+// Meshes instanced by multiple nodes are only uploaded once.
+#[snoozy]
+pub async fn upload_gltf_scene_snoozy(
+    mut ctx: Context,
+    scene_ref: &SnoozyRef<GltfScene>,
+    overrides: &GltfSceneOverrides,
+) -> Result<ShaderUniformBundle> {
+    let scene = ctx.get(scene_ref).await?;
+
+    Ok(scene
+        .mesh_instances(overrides)
+        .into_iter()
+        .map(|(mesh, xform)| {
+            shader_uniform_bundle!(
+                instance_transform: upload_buffer(xform),
+                :upload_raster_mesh(make_raster_mesh(gltf_scene_mesh(scene_ref.clone(), mesh)))
+            )
+        })
+        .collect())
+}
+
+// Applies morph targets and linear blend skinning to the vertices of `mesh` in a compute pass.
+// Skinning is only applied if `skinned` is set, in which case `joint_matrix_buf` holds
+// a `Matrix4` per joint, followed by the world transform of the mesh node, which is used for
+// vertices without skin weights. Otherwise the result is in the same space as the rest pose.
+// `morph_weight_buf` holds a float per morph target. Both usually change every frame,
+// so they're passed as buffers, which keeps the arguments of this op cheap to hash.
+#[snoozy]
+pub async fn deform_raster_mesh_snoozy(
+    mut ctx: Context,
+    mesh: &SnoozyRef<RasterGpuMesh>,
+    deformation: &SnoozyRef<GltfMeshDeformation>,
+    skinned: &bool,
+    joint_matrix_buf: &SnoozyRef<Buffer>,
+    morph_weight_buf: &SnoozyRef<Buffer>,
+) -> Result<Buffer> {
+    let mesh = ctx.get(mesh).await?;
+    let deformation = ctx.get(deformation).await?;
+
+    let vertex_count = mesh.verts.len();
+    let skinned = *skinned && deformation.is_skinned();
+    let morph_target_count = deformation.morph_target_count;
+
+    // Vulkan doesn't allow empty buffers, so pad the unused ones
+    let skin_joint_buf = if skinned {
+        upload_array_buffer(ArcView::new(&deformation, |d| &d.joints))
+    } else {
+        upload_array_buffer(Box::new(vec![[0u32; 4]]))
+    };
+
+    let skin_weight_buf = if skinned {
+        upload_array_buffer(ArcView::new(&deformation, |d| &d.weights))
+    } else {
+        upload_array_buffer(Box::new(vec![[0.0f32; 4]]))
+    };
+
+    let morph_delta_buf = if morph_target_count > 0 {
+        upload_array_buffer(ArcView::new(&deformation, |d| &d.morph_deltas))
+    } else {
+        upload_array_buffer(Box::new(vec![[0.0f32; 6]]))
+    };
+
+    let deformed = compute_buffer(
+        BufferKey::new(vertex_count * std::mem::size_of::<RasterGpuVertex>(), None),
+        (vertex_count as u32, 1, 1),
+        load_cs(crate::asset!("shaders/deform_mesh.glsl")),
+        shader_uniforms!(
+            vertex_count: vertex_count as u32,
+            morph_target_count: morph_target_count,
+            skinned: skinned as u32,
+            rest_vertex_buf: upload_array_buffer(ArcView::new(&mesh, |m| &m.verts)),
+            skin_joint_buf: skin_joint_buf,
+            skin_weight_buf: skin_weight_buf,
+            morph_delta_buf: morph_delta_buf,
+            morph_weight_buf: morph_weight_buf.clone(),
+            joint_matrix_buf: joint_matrix_buf.clone(),
+        ),
+    );
+
+    Ok((*ctx.get(deformed).await?).clone())
+}
+
+// Like `upload_gltf_scene`, but with the scene posed by an animation clip sampled at `time`.
+// Skinned and morphed meshes are deformed on the GPU every time this is evaluated.
+#[snoozy]
+pub async fn upload_animated_gltf_scene_snoozy(
+    mut ctx: Context,
+    scene_ref: &SnoozyRef<GltfScene>,
+    overrides: &GltfSceneOverrides,
+    clip: &usize,
+    time: &SnoozyRef<f32>,
+) -> Result<ShaderUniformBundle> {
+    let scene = ctx.get(scene_ref).await?;
+    let time = *ctx.get(time).await?;
+
+    let pose = scene.sample_pose(*clip, time);
+    let node_xforms = scene.node_world_transforms(&pose, overrides);
+
+    Ok(node_xforms
+        .iter()
+        .filter(|(_, _, visible)| *visible)
+        .filter_map(|(node_idx, xform, _)| {
+            let node = &scene.nodes[*node_idx];
+            let mesh = node.mesh? as usize;
+            let raster_mesh = make_raster_mesh(gltf_scene_mesh(scene_ref.clone(), mesh));
+
+            if scene.deformations[mesh].is_empty() {
+                return Some(shader_uniform_bundle!(
+                    instance_transform: upload_buffer(*xform),
+                    :upload_raster_mesh(raster_mesh)
+                ));
+            }
+
+            // Skinned vertices end up in world space, so the instance transform is identity.
+            // Vertices without weights (e.g. of primitives lacking `JOINTS_0`) are transformed
+            // by the node's world transform instead, which goes after the joint matrices.
+            let skin = node.skin.filter(|_| scene.deformations[mesh].is_skinned());
+            let joint_matrices = skin
+                .map(|skin| scene.skin_joint_matrices(skin as usize, &node_xforms))
+                .filter(|joint_matrices| !joint_matrices.is_empty())
+                .map(|mut joint_matrices| {
+                    joint_matrices.push(*xform);
+                    joint_matrices
+                });
+            let skinned = joint_matrices.is_some();
+            let instance_xform = if skinned { Matrix4::identity() } else { *xform };
+            let joint_matrices = joint_matrices.unwrap_or_else(|| vec![Matrix4::identity()]);
+
+            // Vulkan doesn't allow empty buffers, so pad the weights for meshes without targets
+            let mut morph_weights = pose.nodes[*node_idx].morph_weights.clone();
+            morph_weights.resize(
+                (scene.deformations[mesh].morph_target_count as usize).max(1),
+                0.0,
+            );
+
+            let vertex_buf = deform_raster_mesh(
+                raster_mesh.clone(),
+                gltf_scene_mesh_deformation(scene_ref.clone(), mesh),
+                skinned,
+                upload_array_buffer(Box::new(joint_matrices)),
+                upload_array_buffer(Box::new(morph_weights)),
+            );
+
+            Some(shader_uniform_bundle!(
+                instance_transform: upload_buffer(instance_xform),
+                :upload_deformed_raster_mesh(raster_mesh, vertex_buf)
+            ))
+        })
+        .collect())
+}
+
+const GPU_LIGHT_TYPE_DIRECTIONAL: u32 = 0;
+const GPU_LIGHT_TYPE_POINT: u32 = 1;
+const GPU_LIGHT_TYPE_SPOT: u32 = 2;
+
+// Must match `PunctualLight` in `assets/shaders/punctual_light.inc`.
+// All scalars, so the std430 layout is the same as `repr(C)`.
+#[derive(Copy, Clone, Serialize)]
+#[repr(C)]
+struct GpuLight {
+    position: [f32; 3],
+    light_type: u32,
+    direction: [f32; 3],
+    // Zero if infinite
+    range: f32,
+    // Color multiplied by intensity
+    radiance: [f32; 3],
+    // Spot cone attenuation is `saturate(cos_angle * scale + offset)`
+    spot_angle_scale: f32,
+    spot_angle_offset: f32,
+}
+
+impl GpuLight {
+    fn new(light: &GltfLight, world_xform: &Matrix4, scene_scale: f32) -> Self {
+        let position = Point3::from_homogeneous(world_xform * Point3::origin().to_homogeneous())
+            .unwrap_or_else(Point3::origin);
+        let direction = (world_xform * na::Vector4::new(0.0, 0.0, -1.0, 0.0))
+            .xyz()
+            .normalize();
+
+        let (spot_angle_scale, spot_angle_offset) = if light.light_type == GltfLightType::Spot {
+            let cos_inner = light.inner_cone_angle.cos();
+            let cos_outer = light.outer_cone_angle.cos();
+            let scale = 1.0 / (cos_inner - cos_outer).max(1e-3);
+            (scale, -cos_outer * scale)
+        } else {
+            (0.0, 1.0)
+        };
+
+        Self {
+            position: [position.x, position.y, position.z],
+            light_type: match light.light_type {
+                GltfLightType::Directional => GPU_LIGHT_TYPE_DIRECTIONAL,
+                GltfLightType::Point => GPU_LIGHT_TYPE_POINT,
+                GltfLightType::Spot => GPU_LIGHT_TYPE_SPOT,
+            },
+            direction: [direction.x, direction.y, direction.z],
+            range: light.range.map(|r| r * scene_scale).unwrap_or(0.0),
+            radiance: [
+                light.color[0] * light.intensity,
+                light.color[1] * light.intensity,
+                light.color[2] * light.intensity,
+            ],
+            spot_angle_scale,
+            spot_angle_offset,
+        }
+    }
+}
+
+// Uploads all visible lights of the scene as `light_buf`, with their number in `light_count`.
+#[snoozy]
+pub async fn upload_gltf_scene_lights_snoozy(
+    mut ctx: Context,
+    scene: &SnoozyRef<GltfScene>,
+    overrides: &GltfSceneOverrides,
+) -> Result<ShaderUniformBundle> {
+    let scene = ctx.get(scene).await?;
+
+    let mut lights: Vec<GpuLight> = scene
+        .light_instances(overrides)
+        .into_iter()
+        .filter_map(|(light, xform)| {
+            scene
+                .lights
+                .get(light)
+                .map(|light| GpuLight::new(light, &xform, scene.scale))
+        })
+        .collect();
+
+    let light_count = lights.len() as u32;
+
+    // Vulkan doesn't allow empty buffers
+    if lights.is_empty() {
+        lights.push(GpuLight::new(
+            &GltfLight {
+                name: None,
+                light_type: GltfLightType::Point,
+                color: [0.0; 3],
+                intensity: 0.0,
+                range: None,
+                inner_cone_angle: 0.0,
+                outer_cone_angle: 0.0,
+            },
+            &Matrix4::identity(),
+            1.0,
+        ));
+    }
+
+    Ok(shader_uniforms!(
+        light_count: light_count,
+        light_buf: upload_array_buffer(Box::new(lights)),
+    ))
+}
@@ -0,0 +1,183 @@
+use super::*;
+
+// Projected size of a mesh's bounding sphere, as a fraction of the viewport height,
+// at which it starts switching to coarser LODs. Each LOD then takes over when the projected
+// area shrinks by as much as its triangle count did, keeping triangle density constant.
+const MESH_LOD_FULL_DETAIL_SCREEN_SIZE: f32 = 0.5;
+
+// Maximum simplification error, relative to the extents of the mesh
+const MESH_LOD_MAX_ERROR: f32 = 0.05;
+
+#[derive(Clone, Abomonation)]
+pub struct MeshLodChain {
+    // Most detailed first; `lods[0]` is the source mesh
+    pub lods: Vec<TriangleMesh>,
+    // Triangle count of every LOD relative to the source mesh
+    pub triangle_ratios: Vec<f32>,
+    // Bounding sphere of the source mesh
+    pub bounds_center: [f32; 3],
+    pub bounds_radius: f32,
+}
+
+impl MeshLodChain {
+    // Picks the LOD to draw an instance with based on its projected size on screen
+    pub fn select_lod(&self, instance_xform: &Matrix4, camera: &CameraMatrices) -> usize {
+        let center = Point3::from(self.bounds_center);
+        let center = Point3::from_homogeneous(
+            camera.world_to_view * instance_xform * center.to_homogeneous(),
+        )
+        .unwrap_or_else(Point3::origin);
+
+        let max_scale = (0..3)
+            .map(|i| instance_xform.fixed_slice::<na::U3, na::U1>(0, i).norm())
+            .fold(0.0f32, f32::max);
+        let radius = self.bounds_radius * max_scale;
+
+        let clip_w = (camera.view_to_clip * center.to_homogeneous()).w;
+
+        // Camera inside of the bounding sphere
+        if center.coords.norm() <= radius || clip_w <= 0.0 {
+            return 0;
+        }
+
+        let screen_size = radius * camera.view_to_clip.m22.abs() / clip_w;
+
+        self.triangle_ratios
+            .iter()
+            .rposition(|ratio| MESH_LOD_FULL_DETAIL_SCREEN_SIZE * ratio.sqrt() >= screen_size)
+            .unwrap_or(0)
+    }
+}
+
+impl TriangleMesh {
+    // Reduces the triangle count to roughly `target_ratio` of the original via quadric error
+    // metric edge collapses. Vertices on UV seams and material boundaries, which are split
+    // in `TriangleMesh`, only collapse along the seam, so the boundaries are kept intact.
+    // Simplification stops short of the target if that would exceed `MESH_LOD_MAX_ERROR`.
+    pub fn simplify(&self, target_ratio: f32) -> Result<TriangleMesh> {
+        let mut res = self.clone();
+
+        let target_index_count = ((self.indices.len() / 3) as f32 * target_ratio) as usize * 3;
+        let vertices = meshopt::VertexDataAdapter::new(
+            meshopt::typed_to_bytes(&self.positions),
+            std::mem::size_of::<[f32; 3]>(),
+            0,
+        )?;
+
+        res.indices = meshopt::simplify(
+            &self.indices,
+            &vertices,
+            target_index_count,
+            MESH_LOD_MAX_ERROR,
+        );
+
+        res.optimize_triangle_order()?;
+        res.optimize_vertex_order();
+
+        Ok(res)
+    }
+
+    fn bounding_sphere(&self) -> (Point3, f32) {
+        if self.positions.is_empty() {
+            return (Point3::origin(), 0.0);
+        }
+
+        let mut min = Vector3::repeat(std::f32::MAX);
+        let mut max = Vector3::repeat(-std::f32::MAX);
+
+        for p in self.positions.iter() {
+            let p = Vector3::from(*p);
+            min = min.zip_map(&p, f32::min);
+            max = max.zip_map(&p, f32::max);
+        }
+
+        let center = Point3::from((min + max) * 0.5);
+        let radius = self
+            .positions
+            .iter()
+            .map(|p| (Point3::from(*p) - center).norm())
+            .fold(0.0f32, f32::max);
+
+        (center, radius)
+    }
+}
+
+// Builds a chain of simplified versions of `mesh` with triangle counts of roughly
+// `target_ratios` (e.g. `vec![0.5, 0.25, 0.125]`) of the original. The chain is cut short
+// when simplification can't make meaningful progress anymore.
+#[snoozy(cache)]
+pub async fn build_mesh_lods_snoozy(
+    mut ctx: Context,
+    mesh: &SnoozyRef<TriangleMesh>,
+    target_ratios: &Vec<f32>,
+) -> Result<MeshLodChain> {
+    let mesh = ctx.get(mesh).await?;
+    let (bounds_center, bounds_radius) = mesh.bounding_sphere();
+
+    let triangle_count = (mesh.indices.len() / 3).max(1) as f32;
+    let mut lods = vec![(*mesh).clone()];
+    let mut triangle_ratios = vec![1.0f32];
+
+    // Point lists can't be simplified
+    if mesh.topology == MeshTopology::Triangles {
+        for target_ratio in target_ratios.iter() {
+            let lod = mesh.simplify(*target_ratio)?;
+            let ratio = (lod.indices.len() / 3) as f32 / triangle_count;
+
+            if lod.indices.is_empty() || ratio > triangle_ratios.last().unwrap() * 0.9 {
+                break;
+            }
+
+            lods.push(lod);
+            triangle_ratios.push(ratio);
+        }
+    }
+
+    Ok(MeshLodChain {
+        lods,
+        triangle_ratios,
+        bounds_center: [bounds_center.x, bounds_center.y, bounds_center.z],
+        bounds_radius,
+    })
+}
+
+#[snoozy]
+pub async fn mesh_lod_snoozy(
+    mut ctx: Context,
+    chain: &SnoozyRef<MeshLodChain>,
+    lod: &usize,
+) -> Result<TriangleMesh> {
+    let chain = ctx.get(chain).await?;
+    chain
+        .lods
+        .get(*lod)
+        .cloned()
+        .ok_or_else(|| format_err!("LOD {} out of range", *lod))
+}
+
+// Like `upload_raster_scene`, but draws every instance with the LOD matching its size on screen.
+// `camera` is meant to be redefined every frame, e.g. with `const_camera_matrices`.
+#[snoozy]
+pub async fn upload_raster_scene_lods_snoozy(
+    mut ctx: Context,
+    scene: &Vec<(SnoozyRef<MeshLodChain>, Vector3, UnitQuaternion)>,
+    camera: &SnoozyRef<CameraMatrices>,
+) -> Result<ShaderUniformBundle> {
+    let camera = ctx.get(camera).await?;
+
+    let mut res = Vec::with_capacity(scene.len());
+
+    for (chain_ref, position, rotation) in scene.iter() {
+        let chain = ctx.get(chain_ref).await?;
+
+        let xform = Matrix4::new_translation(position) * rotation.to_homogeneous();
+        let lod = chain.select_lod(&xform, &camera);
+
+        res.push(shader_uniform_bundle!(
+            instance_transform: raster_mesh_transform(*position, *rotation),
+            :upload_raster_mesh(make_raster_mesh(mesh_lod(chain_ref.clone(), lod)))
+        ));
+    }
+
+    Ok(res.into_iter().collect())
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+// Applies a meshoptimizer old-to-new vertex remap table to all vertex attributes of the mesh
+fn remap_mesh_vertices(mesh: &mut TriangleMesh, vertex_count: usize, remap: &[u32]) {
+    mesh.indices = meshopt::remap_index_buffer(Some(&mesh.indices), vertex_count, remap);
+    mesh.positions = meshopt::remap_vertex_buffer(&mesh.positions, vertex_count, remap);
+    mesh.normals = meshopt::remap_vertex_buffer(&mesh.normals, vertex_count, remap);
+    mesh.colors = meshopt::remap_vertex_buffer(&mesh.colors, vertex_count, remap);
+    mesh.uvs = meshopt::remap_vertex_buffer(&mesh.uvs, vertex_count, remap);
+    mesh.tangents = meshopt::remap_vertex_buffer(&mesh.tangents, vertex_count, remap);
+    mesh.material_ids = meshopt::remap_vertex_buffer(&mesh.material_ids, vertex_count, remap);
+}
+
+impl TriangleMesh {
+    // Merges binary-identical vertices, and drops ones which aren't referenced by any index.
+    pub fn deduplicate_vertices(&mut self) {
+        let vertex_count = self.positions.len();
+        if vertex_count == 0 {
+            return;
+        }
+
+        // meshoptimizer reads every stream for every vertex
+        self.normals.resize(vertex_count, [0.0, 1.0, 0.0]);
+        self.colors.resize(vertex_count, [1.0, 1.0, 1.0, 1.0]);
+        self.uvs.resize(vertex_count, [0.0, 0.0]);
+        self.tangents.resize(vertex_count, [1.0, 0.0, 0.0, 1.0]);
+        self.material_ids.resize(vertex_count, 0);
+
+        let streams = [
+            meshopt::VertexStream::new(self.positions.as_ptr()),
+            meshopt::VertexStream::new(self.normals.as_ptr()),
+            meshopt::VertexStream::new(self.colors.as_ptr()),
+            meshopt::VertexStream::new(self.uvs.as_ptr()),
+            meshopt::VertexStream::new(self.tangents.as_ptr()),
+            meshopt::VertexStream::new(self.material_ids.as_ptr()),
+        ];
+
+        // The type parameter of `generate_vertex_remap_multi` is unused
+        let (unique_vertex_count, remap) =
+            meshopt::generate_vertex_remap_multi::<u8>(vertex_count, &streams, Some(&self.indices));
+
+        remap_mesh_vertices(self, unique_vertex_count, &remap);
+    }
+
+    // Reorders triangles for post-transform vertex cache efficiency, and then to reduce overdraw.
+    pub fn optimize_triangle_order(&mut self) -> Result<()> {
+        // Point lists can't be reordered as triangles
+        if self.topology != MeshTopology::Triangles {
+            return Ok(());
+        }
+
+        self.indices = meshopt::optimize_vertex_cache(&self.indices, self.positions.len());
+
+        // Allows the vertex cache hit ratio to get 5% worse in favor of less overdraw
+        let vertices = meshopt::VertexDataAdapter::new(
+            meshopt::typed_to_bytes(&self.positions),
+            std::mem::size_of::<[f32; 3]>(),
+            0,
+        )?;
+        meshopt::optimize_overdraw_in_place(&self.indices, &vertices, 1.05);
+
+        Ok(())
+    }
+
+    // Reorders vertices in the order they're first referenced by the index buffer.
+    // Vertices which aren't referenced at all are dropped.
+    pub fn optimize_vertex_order(&mut self) {
+        let mut remap = vec![!0u32; self.positions.len()];
+        let mut next_vertex = 0u32;
+
+        for i in self.indices.iter() {
+            let slot = &mut remap[*i as usize];
+            if *slot == !0u32 {
+                *slot = next_vertex;
+                next_vertex += 1;
+            }
+        }
+
+        remap_mesh_vertices(self, next_vertex as usize, &remap);
+    }
+}
+
+// Runs all of the above. Vertex order changes, so this must not be used for meshes
+// with extra per-vertex data stored elsewhere, e.g. `GltfMeshDeformation`.
+#[snoozy(cache)]
+pub async fn optimize_mesh_snoozy(
+    mut ctx: Context,
+    mesh: &SnoozyRef<TriangleMesh>,
+) -> Result<TriangleMesh> {
+    let mut mesh = (*ctx.get(mesh).await?).clone();
+
+    mesh.deduplicate_vertices();
+    mesh.optimize_triangle_order()?;
+    mesh.optimize_vertex_order();
+
+    Ok(mesh)
+}
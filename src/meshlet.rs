@@ -0,0 +1,88 @@
+use super::*;
+
+pub const MESHLET_MAX_VERTICES: usize = 64;
+pub const MESHLET_MAX_TRIANGLES: usize = 124;
+
+// Must match `Meshlet` in `assets/shaders/meshlet.inc`.
+// All scalars, so the std430 layout is the same as `repr(C)`.
+#[derive(Clone, Copy, Abomonation, Serialize)]
+#[repr(C)]
+pub struct GpuMeshlet {
+    pub center: [f32; 3],
+    pub radius: f32,
+    // The meshlet can be culled when `dot(normalize(cone_apex - camera_pos), cone_axis) >= cone_cutoff`
+    pub cone_apex: [f32; 3],
+    pub cone_cutoff: f32,
+    pub cone_axis: [f32; 3],
+    // Range in `MeshletData::vertices`
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    // Range in `MeshletData::triangles`
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+}
+
+#[derive(Clone, Default, Abomonation)]
+pub struct MeshletData {
+    pub meshlets: Vec<GpuMeshlet>,
+    // Indices into the vertices of the mesh
+    pub vertices: Vec<u32>,
+    // Three 8-bit indices into the meshlet's range of `vertices` per triangle
+    pub triangles: Vec<u32>,
+}
+
+impl TriangleMesh {
+    // Splits the mesh into clusters of up to `MESHLET_MAX_VERTICES` vertices and
+    // `MESHLET_MAX_TRIANGLES` triangles. Works best with vertex cache optimized meshes,
+    // e.g. the output of `optimize_mesh`.
+    pub fn build_meshlets(&self) -> Result<MeshletData> {
+        let mut res = MeshletData::default();
+
+        // Point lists have no triangles to cluster
+        if self.indices.is_empty() || self.topology != MeshTopology::Triangles {
+            return Ok(res);
+        }
+
+        let vertices = meshopt::VertexDataAdapter::new(
+            meshopt::typed_to_bytes(&self.positions),
+            std::mem::size_of::<[f32; 3]>(),
+            0,
+        )?;
+
+        let meshlets = meshopt::build_meshlets(
+            &self.indices,
+            self.positions.len(),
+            MESHLET_MAX_VERTICES,
+            MESHLET_MAX_TRIANGLES,
+        );
+
+        for meshlet in meshlets.iter() {
+            let bounds = meshopt::compute_meshlet_bounds(meshlet, &vertices);
+
+            let vertex_count = meshlet.vertex_count as usize;
+            let triangle_count = meshlet.triangle_count as usize;
+
+            res.meshlets.push(GpuMeshlet {
+                center: bounds.center,
+                radius: bounds.radius,
+                cone_apex: bounds.cone_apex,
+                cone_cutoff: bounds.cone_cutoff,
+                cone_axis: bounds.cone_axis,
+                vertex_offset: res.vertices.len() as u32,
+                vertex_count: vertex_count as u32,
+                triangle_offset: res.triangles.len() as u32,
+                triangle_count: triangle_count as u32,
+            });
+
+            res.vertices
+                .extend_from_slice(&meshlet.vertices[..vertex_count]);
+            res.triangles.extend(
+                meshlet.indices[..triangle_count]
+                    .iter()
+                    .map(|t| t[0] as u32 | (t[1] as u32) << 8 | (t[2] as u32) << 16),
+            );
+        }
+
+        Ok(res)
+    }
+}
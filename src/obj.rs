@@ -0,0 +1,418 @@
+use super::*;
+use std::collections::HashMap;
+
+struct ObjMaterial {
+    name: String,
+    diffuse: [f32; 3],
+    emissive: [f32; 3],
+    dissolve: f32,
+    specular_exponent: Option<f32>,
+    diffuse_map: Option<String>,
+    normal_map: Option<String>,
+    specular_exponent_map: Option<String>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: [0.8, 0.8, 0.8],
+            emissive: [0.0, 0.0, 0.0],
+            dissolve: 1.0,
+            specular_exponent: None,
+            diffuse_map: None,
+            normal_map: None,
+            specular_exponent_map: None,
+        }
+    }
+}
+
+fn parse_obj_floats<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+    line_idx: usize,
+) -> Result<Vec<f32>> {
+    tokens
+        .map(|t| {
+            t.parse::<f32>()
+                .map_err(|_| format_err!("line {}: invalid number {:?}", line_idx + 1, t))
+        })
+        .collect()
+}
+
+fn parse_obj_vec3(values: &[f32], line_idx: usize) -> Result<[f32; 3]> {
+    if values.len() < 3 {
+        bail!("line {}: expected 3 components", line_idx + 1);
+    }
+    Ok([values[0], values[1], values[2]])
+}
+
+// Texture statements may be preceded by options such as `-bm 0.5`. The file name comes last.
+fn parse_mtl_map_path(rest: &str) -> Option<String> {
+    rest.split_whitespace()
+        .last()
+        .map(|path| path.replace('\\', "/"))
+}
+
+fn has_mtl_map_option(rest: &str, option: &str) -> bool {
+    let mut tokens: Vec<&str> = rest.split_whitespace().collect();
+    tokens.pop();
+    tokens.contains(&option)
+}
+
+fn parse_mtl(source: &str, mtl_path: &AssetPath) -> Result<Vec<ObjMaterial>> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let line = line.trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = if let Some(keyword) = tokens.next() {
+            keyword
+        } else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial::new(line[keyword.len()..].trim()));
+            continue;
+        }
+
+        let material = if let Some(material) = materials.last_mut() {
+            material
+        } else {
+            continue;
+        };
+
+        let rest = line[keyword.len()..].trim();
+
+        match keyword {
+            "Kd" => {
+                material.diffuse = parse_obj_vec3(&parse_obj_floats(tokens, line_idx)?, line_idx)?
+            }
+            "Ke" => {
+                material.emissive = parse_obj_vec3(&parse_obj_floats(tokens, line_idx)?, line_idx)?
+            }
+            "d" => {
+                if let Some(d) = parse_obj_floats(tokens, line_idx)?.first() {
+                    material.dissolve = *d;
+                }
+            }
+            "Tr" => {
+                if let Some(tr) = parse_obj_floats(tokens, line_idx)?.first() {
+                    material.dissolve = 1.0 - *tr;
+                }
+            }
+            "Ns" => {
+                material.specular_exponent = parse_obj_floats(tokens, line_idx)?.first().cloned()
+            }
+            "map_Kd" => material.diffuse_map = parse_mtl_map_path(rest),
+            "norm" => material.normal_map = parse_mtl_map_path(rest),
+            // Bump maps are usually height maps, which would make for garbage normals.
+            // Exporters which write normal maps there mark them with a bump multiplier.
+            "map_Bump" | "map_bump" | "bump" => {
+                if has_mtl_map_option(rest, "-bm") {
+                    if material.normal_map.is_none() {
+                        material.normal_map = parse_mtl_map_path(rest);
+                    }
+                } else {
+                    tracing::warn!(
+                        "{}: line {}: ignoring bump map {:?} of material {:?}; height maps aren't supported, and normal maps must be marked with -bm",
+                        mtl_path,
+                        line_idx + 1,
+                        parse_mtl_map_path(rest).unwrap_or_default(),
+                        material.name
+                    );
+                }
+            }
+            "map_Ns" => material.specular_exponent_map = parse_mtl_map_path(rest),
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+// Paths in OBJ and MTL files are relative to the file referencing them
+fn make_obj_relative_asset_path(parent_path: &AssetPath, path: &str) -> AssetPath {
+    let mut asset_name: std::path::PathBuf = parent_path.asset_name.clone().into();
+    asset_name.pop();
+    asset_name.push(path);
+    AssetPath {
+        crate_name: parent_path.crate_name.clone(),
+        asset_name: asset_name.to_string_lossy().to_string(),
+    }
+}
+
+fn convert_obj_material(
+    mat: &ObjMaterial,
+    mtl_path: &AssetPath,
+) -> (Vec<MeshMaterialMap>, MeshMaterial) {
+    let make_material_map = |path: &Option<String>, gamma: TexGamma, placeholder: [u8; 4]| {
+        if let Some(path) = path {
+            MeshMaterialMap::Asset {
+                path: make_obj_relative_asset_path(mtl_path, path),
+                params: TexParams {
+                    gamma,
+                    channels: TexChannels::Rgba,
+                    bit_depth: TexBitDepth::U8,
+                },
+            }
+        } else {
+            MeshMaterialMap::Placeholder(placeholder)
+        }
+    };
+
+    let mut maps =
+        vec![MeshMaterialMap::Placeholder([255, 255, 255, 255]); MESH_MATERIAL_MAP_COUNT];
+    maps[MESH_MATERIAL_MAP_ALBEDO] =
+        make_material_map(&mat.diffuse_map, TexGamma::Srgb, [255, 255, 255, 255]);
+    maps[MESH_MATERIAL_MAP_NORMAL] =
+        make_material_map(&mat.normal_map, TexGamma::Linear, [127, 127, 255, 255]);
+
+    // `map_Ns` scales `Ns`, and goes into the metallic-roughness map. Shaders convert it
+    // to roughness via `mesh_material_roughness` in `assets/shaders/mesh_material.inc`.
+    // Without `Ns`, the map is taken to span the usual exponent range of 0 to 1000.
+    let specular_exponent_map = mat.specular_exponent_map.is_some();
+    maps[MESH_MATERIAL_MAP_METALLIC_ROUGHNESS] = make_material_map(
+        &mat.specular_exponent_map,
+        TexGamma::Linear,
+        [255, 255, 255, 255],
+    );
+
+    let specular_exponent = if specular_exponent_map {
+        mat.specular_exponent.or(Some(1000.0))
+    } else {
+        mat.specular_exponent
+    };
+
+    // Blinn-Phong exponent to GGX roughness
+    let roughness = specular_exponent
+        .map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt())
+        .unwrap_or(1.0);
+
+    let mut map_ids = [0u32; MESH_MATERIAL_MAP_COUNT];
+    for (i, id) in map_ids.iter_mut().enumerate() {
+        *id = i as u32;
+    }
+
+    (
+        maps,
+        MeshMaterial {
+            base_color_mult: [mat.diffuse[0], mat.diffuse[1], mat.diffuse[2], mat.dissolve],
+            maps: map_ids,
+            map_transforms: [IDENTITY_UV_TRANSFORM; MESH_MATERIAL_MAP_COUNT],
+            emissive: mat.emissive,
+            metallic_mult: 0.0,
+            roughness_mult: roughness,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_mode: if mat.dissolve < 1.0 {
+                MeshAlphaMode::Blend
+            } else {
+                MeshAlphaMode::Opaque
+            },
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            specular_exponent_map,
+        },
+    )
+}
+
+// Resolves a 1-based or negative (relative) OBJ index
+fn resolve_obj_index(idx: &str, count: usize, line_idx: usize) -> Result<usize> {
+    let i: i64 = idx
+        .parse()
+        .map_err(|_| format_err!("line {}: invalid index {:?}", line_idx + 1, idx))?;
+
+    let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+
+    if resolved < 0 || resolved >= count as i64 {
+        bail!("line {}: index {} out of range", line_idx + 1, i);
+    }
+
+    Ok(resolved as usize)
+}
+
+#[snoozy(cache)]
+pub async fn load_obj_scene_snoozy(
+    mut ctx: Context,
+    path: &AssetPath,
+    scale: &f32,
+) -> Result<TriangleMesh> {
+    let obj_blob = ctx.get(&load_blob(path.clone())).await?;
+    let source = String::from_utf8_lossy(&obj_blob.contents);
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut res = TriangleMesh::default();
+    let mut material_lookup: HashMap<String, u32> = HashMap::new();
+    let mut current_material: Option<u32> = None;
+    let mut default_material: Option<u32> = None;
+
+    // Maps (position, uv, normal, material) to an output vertex
+    let mut vertex_lookup: HashMap<(usize, Option<usize>, Option<usize>, u32), u32> =
+        HashMap::new();
+    let mut has_normal: Vec<bool> = Vec::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let line = line.trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = if let Some(keyword) = tokens.next() {
+            keyword
+        } else {
+            continue;
+        };
+
+        match keyword {
+            "v" => {
+                let v = parse_obj_floats(tokens, line_idx)?;
+                positions.push(parse_obj_vec3(&v, line_idx)?);
+
+                // Vertex colors are a common extension: `v x y z r g b`
+                colors.push(if v.len() >= 6 {
+                    [v[3], v[4], v[5], 1.0]
+                } else {
+                    [1.0, 1.0, 1.0, 1.0]
+                });
+            }
+            "vt" => {
+                let v = parse_obj_floats(tokens, line_idx)?;
+                let u = v.get(0).cloned().unwrap_or(0.0);
+                let v = v.get(1).cloned().unwrap_or(0.0);
+
+                // OBJ has the origin at the bottom, but we use the glTF convention
+                uvs.push([u, 1.0 - v]);
+            }
+            "vn" => {
+                normals.push(parse_obj_vec3(
+                    &parse_obj_floats(tokens, line_idx)?,
+                    line_idx,
+                )?);
+            }
+            "mtllib" => {
+                let mtl_path = make_obj_relative_asset_path(path, line[keyword.len()..].trim());
+
+                // Faces using the missing materials get the default one
+                let mtl_blob = match ctx.get(&load_blob(mtl_path.clone())).await {
+                    Ok(mtl_blob) => mtl_blob,
+                    Err(err) => {
+                        tracing::warn!("{}: failed to load {}: {}", path, mtl_path, err);
+                        continue;
+                    }
+                };
+
+                let materials = parse_mtl(&String::from_utf8_lossy(&mtl_blob.contents), &mtl_path)
+                    .map_err(|err| format_err!("{}: {}", mtl_path, err))?;
+
+                for mat in materials.iter() {
+                    let (mut maps, mut material) = convert_obj_material(mat, &mtl_path);
+
+                    let map_base = res.maps.len() as u32;
+                    for id in material.maps.iter_mut() {
+                        *id += map_base;
+                    }
+
+                    material_lookup.insert(mat.name.clone(), res.materials.len() as u32);
+                    res.materials.push(material);
+                    res.maps.append(&mut maps);
+                }
+            }
+            "usemtl" => {
+                let name = line[keyword.len()..].trim();
+                current_material = material_lookup.get(name).cloned();
+
+                if current_material.is_none() {
+                    tracing::warn!("{}: unknown material {:?}", path, name);
+                }
+            }
+            "f" => {
+                let material_id = match current_material.or(default_material) {
+                    Some(id) => id,
+                    None => {
+                        // Faces without a known material get a default one
+                        let (mut maps, mut material) =
+                            convert_obj_material(&ObjMaterial::new(""), path);
+
+                        let map_base = res.maps.len() as u32;
+                        for id in material.maps.iter_mut() {
+                            *id += map_base;
+                        }
+
+                        let id = res.materials.len() as u32;
+                        res.materials.push(material);
+                        res.maps.append(&mut maps);
+                        default_material = Some(id);
+                        id
+                    }
+                };
+
+                let mut face: Vec<u32> = Vec::new();
+
+                for vertex in tokens {
+                    let mut parts = vertex.split('/');
+
+                    let pos_idx =
+                        resolve_obj_index(parts.next().unwrap_or(""), positions.len(), line_idx)?;
+
+                    let uv_idx = match parts.next() {
+                        Some(idx) if !idx.is_empty() => {
+                            Some(resolve_obj_index(idx, uvs.len(), line_idx)?)
+                        }
+                        _ => None,
+                    };
+
+                    let normal_idx = match parts.next() {
+                        Some(idx) if !idx.is_empty() => {
+                            Some(resolve_obj_index(idx, normals.len(), line_idx)?)
+                        }
+                        _ => None,
+                    };
+
+                    let key = (pos_idx, uv_idx, normal_idx, material_id);
+                    let out_idx = if let Some(idx) = vertex_lookup.get(&key) {
+                        *idx
+                    } else {
+                        let idx = res.positions.len() as u32;
+                        let p = positions[pos_idx];
+
+                        res.positions
+                            .push([p[0] * scale, p[1] * scale, p[2] * scale]);
+                        res.colors.push(colors[pos_idx]);
+                        res.uvs.push(uv_idx.map(|i| uvs[i]).unwrap_or([0.0, 0.0]));
+                        res.normals
+                            .push(normal_idx.map(|i| normals[i]).unwrap_or([0.0, 0.0, 0.0]));
+                        res.tangents.push([1.0, 0.0, 0.0, 0.0]);
+                        res.material_ids.push(material_id);
+                        has_normal.push(normal_idx.is_some());
+
+                        vertex_lookup.insert(key, idx);
+                        idx
+                    };
+
+                    face.push(out_idx);
+                }
+
+                if face.len() < 3 {
+                    bail!("{}:{}: face with fewer than 3 vertices", path, line_idx + 1);
+                }
+
+                // Fan triangulation; polygons are assumed to be convex
+                for i in 1..face.len() - 1 {
+                    res.indices
+                        .extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if has_normal.iter().any(|n| !n) {
+        res.generate_normals(NormalGeneration::Smooth, &has_normal);
+    }
+
+    res.generate_tangents(&vec![false; res.positions.len()]);
+
+    Ok(res)
+}
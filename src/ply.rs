@@ -0,0 +1,405 @@
+use super::*;
+
+#[derive(Clone, Copy, Debug)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => PlyScalar::I8,
+            "uchar" | "uint8" => PlyScalar::U8,
+            "short" | "int16" => PlyScalar::I16,
+            "ushort" | "uint16" => PlyScalar::U16,
+            "int" | "int32" => PlyScalar::I32,
+            "uint" | "uint32" => PlyScalar::U32,
+            "float" | "float32" => PlyScalar::F32,
+            "double" | "float64" => PlyScalar::F64,
+            _ => bail!("unknown PLY type {:?}", name),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+
+    // Integer colors are normalized, but everything else is used as-is
+    fn color_scale(self) -> f64 {
+        match self {
+            PlyScalar::U8 => 1.0 / 255.0,
+            PlyScalar::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+enum PlyProperty {
+    Scalar {
+        name: String,
+        ty: PlyScalar,
+    },
+    List {
+        name: String,
+        count_ty: PlyScalar,
+        item_ty: PlyScalar,
+    },
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+struct PlyHeader {
+    format: PlyFormat,
+    elements: Vec<PlyElement>,
+    // Offset of the body in the file
+    body_offset: usize,
+}
+
+fn parse_ply_header(data: &[u8]) -> Result<PlyHeader> {
+    const END_HEADER: &[u8] = b"end_header";
+
+    let header_end = data
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or_else(|| format_err!("missing end_header"))?;
+
+    let body_offset = data[header_end..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map(|p| header_end + p + 1)
+        .unwrap_or_else(|| data.len());
+
+    let header = std::str::from_utf8(&data[..header_end])?;
+    let mut lines = header.lines().map(str::trim);
+
+    if lines.next() != Some("ply") {
+        bail!("not a PLY file");
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => bail!("unknown format {:?}", name),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| format_err!("property outside of an element"))?
+                .properties
+                .push(PlyProperty::List {
+                    name: name.to_string(),
+                    count_ty: PlyScalar::parse(count_ty)?,
+                    item_ty: PlyScalar::parse(item_ty)?,
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| format_err!("property outside of an element"))?
+                .properties
+                .push(PlyProperty::Scalar {
+                    name: name.to_string(),
+                    ty: PlyScalar::parse(ty)?,
+                }),
+            _ => {}
+        }
+    }
+
+    Ok(PlyHeader {
+        format: format.ok_or_else(|| format_err!("missing format"))?,
+        elements,
+        body_offset,
+    })
+}
+
+enum PlyBodyReader<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> PlyBodyReader<'a> {
+    fn read(&mut self, ty: PlyScalar) -> Result<f64> {
+        match self {
+            PlyBodyReader::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| format_err!("unexpected end of file"))?;
+                Ok(token.parse::<f64>()?)
+            }
+            PlyBodyReader::Binary {
+                data,
+                offset,
+                big_endian,
+            } => {
+                let size = ty.size();
+                if *offset + size > data.len() {
+                    bail!("unexpected end of file");
+                }
+
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&data[*offset..*offset + size]);
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                *offset += size;
+
+                let b2 = [bytes[0], bytes[1]];
+                let b4 = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+                Ok(match ty {
+                    PlyScalar::I8 => bytes[0] as i8 as f64,
+                    PlyScalar::U8 => bytes[0] as f64,
+                    PlyScalar::I16 => i16::from_le_bytes(b2) as f64,
+                    PlyScalar::U16 => u16::from_le_bytes(b2) as f64,
+                    PlyScalar::I32 => i32::from_le_bytes(b4) as f64,
+                    PlyScalar::U32 => u32::from_le_bytes(b4) as f64,
+                    PlyScalar::F32 => f32::from_le_bytes(b4) as f64,
+                    PlyScalar::F64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+// Vertex colors in PLY files are generally sRGB-encoded
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Loads a binary or ASCII PLY file. Vertex colors and normals are used if present,
+// and normals are generated for meshes without them. Point clouds, which have no faces,
+// get one index per vertex and `MeshTopology::Points`, and are meant to be drawn
+// with `RasterTopology::Points`.
+#[snoozy(cache)]
+pub async fn load_ply_mesh_snoozy(
+    mut ctx: Context,
+    path: &AssetPath,
+    scale: &f32,
+) -> Result<TriangleMesh> {
+    let blob = ctx.get(&load_blob(path.clone())).await?;
+    let data = &blob.contents;
+
+    let header = parse_ply_header(data).map_err(|err| format_err!("{}: {}", path, err))?;
+
+    let mut reader = match header.format {
+        PlyFormat::Ascii => PlyBodyReader::Ascii(
+            std::str::from_utf8(&data[header.body_offset..])?.split_whitespace(),
+        ),
+        PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => PlyBodyReader::Binary {
+            data: &data[header.body_offset..],
+            offset: 0,
+            big_endian: header.format == PlyFormat::BinaryBigEndian,
+        },
+    };
+
+    let mut res = TriangleMesh::default();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    let mut has_faces = false;
+
+    for element in header.elements.iter() {
+        match element.name.as_str() {
+            "vertex" => {
+                // Every vertex takes up at least a byte, which bounds bogus counts
+                let capacity = element.count.min(data.len());
+                res.positions.reserve(capacity);
+                res.normals.reserve(capacity);
+                res.colors.reserve(capacity);
+                res.uvs.reserve(capacity);
+
+                for _ in 0..element.count {
+                    let mut pos = [0.0f32; 3];
+                    let mut normal = [0.0f32; 3];
+                    let mut color = [1.0f32; 4];
+                    let mut uv = [0.0f32; 2];
+
+                    for prop in element.properties.iter() {
+                        match prop {
+                            PlyProperty::Scalar { name, ty } => {
+                                let v = reader.read(*ty)?;
+                                let color_v = (v * ty.color_scale()) as f32;
+
+                                match name.as_str() {
+                                    "x" => pos[0] = v as f32 * scale,
+                                    "y" => pos[1] = v as f32 * scale,
+                                    "z" => pos[2] = v as f32 * scale,
+                                    "nx" => normal[0] = v as f32,
+                                    "ny" => normal[1] = v as f32,
+                                    "nz" => normal[2] = v as f32,
+                                    "red" | "diffuse_red" | "r" => {
+                                        color[0] = srgb_to_linear(color_v)
+                                    }
+                                    "green" | "diffuse_green" | "g" => {
+                                        color[1] = srgb_to_linear(color_v)
+                                    }
+                                    "blue" | "diffuse_blue" | "b" => {
+                                        color[2] = srgb_to_linear(color_v)
+                                    }
+                                    "alpha" | "a" => color[3] = color_v,
+                                    "u" | "s" | "texture_u" => uv[0] = v as f32,
+                                    "v" | "t" | "texture_v" => uv[1] = 1.0 - v as f32,
+                                    _ => {}
+                                }
+                            }
+                            PlyProperty::List {
+                                count_ty, item_ty, ..
+                            } => {
+                                let count = reader.read(*count_ty)? as usize;
+                                for _ in 0..count {
+                                    reader.read(*item_ty)?;
+                                }
+                            }
+                        }
+                    }
+
+                    res.positions.push(pos);
+                    res.normals.push(normal);
+                    res.colors.push(color);
+                    res.uvs.push(uv);
+                }
+
+                has_normals = element.properties.iter().any(|p| match p {
+                    PlyProperty::Scalar { name, .. } => name == "nx",
+                    _ => false,
+                });
+                has_uvs = element.properties.iter().any(|p| match p {
+                    PlyProperty::Scalar { name, .. } => {
+                        name == "u" || name == "s" || name == "texture_u"
+                    }
+                    _ => false,
+                });
+            }
+            "face" => {
+                has_faces = element.count > 0;
+
+                for _ in 0..element.count {
+                    for prop in element.properties.iter() {
+                        match prop {
+                            PlyProperty::List {
+                                name,
+                                count_ty,
+                                item_ty,
+                            } => {
+                                let count = reader.read(*count_ty)? as usize;
+                                let mut face = Vec::new();
+                                for _ in 0..count {
+                                    face.push(reader.read(*item_ty)? as u32);
+                                }
+
+                                if name != "vertex_indices" && name != "vertex_index" {
+                                    continue;
+                                }
+
+                                if face.iter().any(|i| *i as usize >= res.positions.len()) {
+                                    bail!("{}: face index out of range", path);
+                                }
+
+                                // Fan triangulation; polygons are assumed to be convex
+                                if face.len() >= 3 {
+                                    for i in 1..face.len() - 1 {
+                                        res.indices.extend_from_slice(&[
+                                            face[0],
+                                            face[i],
+                                            face[i + 1],
+                                        ]);
+                                    }
+                                }
+                            }
+                            PlyProperty::Scalar { ty, .. } => {
+                                reader.read(*ty)?;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                // Skip unknown elements
+                for _ in 0..element.count {
+                    for prop in element.properties.iter() {
+                        match prop {
+                            PlyProperty::Scalar { ty, .. } => {
+                                reader.read(*ty)?;
+                            }
+                            PlyProperty::List {
+                                count_ty, item_ty, ..
+                            } => {
+                                let count = reader.read(*count_ty)? as usize;
+                                for _ in 0..count {
+                                    reader.read(*item_ty)?;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let vertex_count = res.positions.len();
+
+    if has_faces {
+        if !has_normals {
+            res.generate_normals(NormalGeneration::Smooth, &vec![false; vertex_count]);
+        }
+    } else {
+        if !has_normals {
+            res.normals = vec![[0.0, 1.0, 0.0]; vertex_count];
+        }
+        res.indices = (0..vertex_count as u32).collect();
+        res.topology = MeshTopology::Points;
+    }
+
+    let material_id = res.add_default_material();
+    res.material_ids = vec![material_id; vertex_count];
+
+    if has_faces && has_uvs {
+        res.generate_tangents(&vec![false; vertex_count]);
+    } else {
+        res.generate_arbitrary_tangents();
+    }
+
+    Ok(res)
+}
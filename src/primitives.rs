@@ -0,0 +1,351 @@
+use super::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// All primitives use a single default material, have white vertex colors, and are centered
+// at the origin, with +Y up. UVs put the top of textures towards +Y (or the start of the
+// second grid parameter below), and tangents are generated with MikkTSpace.
+
+fn new_primitive() -> TriangleMesh {
+    let mut res = TriangleMesh::default();
+    res.add_default_material();
+    res
+}
+
+fn push_vertex(res: &mut TriangleMesh, pos: Vector3, normal: Vector3, uv: [f32; 2]) -> u32 {
+    res.positions.push([pos.x, pos.y, pos.z]);
+    res.normals.push([normal.x, normal.y, normal.z]);
+    res.uvs.push(uv);
+    res.colors.push([1.0, 1.0, 1.0, 1.0]);
+    res.material_ids.push(0);
+    res.positions.len() as u32 - 1
+}
+
+// Adds a grid of `u_segments` by `v_segments` quads. `vertex` maps (u, v) in [0, 1] to
+// a position and a normal; the normal must point along `dpos/du x dpos/dv`
+// for triangles to wind counter-clockwise.
+fn push_grid(
+    res: &mut TriangleMesh,
+    u_segments: u32,
+    v_segments: u32,
+    vertex: impl Fn(f32, f32) -> (Vector3, Vector3),
+) {
+    let base_index = res.positions.len() as u32;
+    let row_len = u_segments + 1;
+
+    for j in 0..=v_segments {
+        let v = j as f32 / v_segments as f32;
+        for i in 0..=u_segments {
+            let u = i as f32 / u_segments as f32;
+            let (pos, normal) = vertex(u, v);
+            push_vertex(res, pos, normal, [u, 1.0 - v]);
+        }
+    }
+
+    for j in 0..v_segments {
+        for i in 0..u_segments {
+            let i0 = base_index + j * row_len + i;
+            let i1 = i0 + 1;
+            let i2 = i0 + row_len + 1;
+            let i3 = i0 + row_len;
+            res.indices.extend_from_slice(&[i0, i1, i2, i0, i2, i3]);
+        }
+    }
+}
+
+fn finish_primitive(mut res: TriangleMesh) -> TriangleMesh {
+    let has_tangent = vec![false; res.positions.len()];
+    res.generate_tangents(&has_tangent);
+    res
+}
+
+// Square in the XZ plane, facing +Y
+#[snoozy]
+pub async fn plane_mesh_snoozy(
+    _ctx: Context,
+    size: &f32,
+    subdivisions: &u32,
+) -> Result<TriangleMesh> {
+    let mut res = new_primitive();
+    let segments = subdivisions + 1;
+
+    push_grid(&mut res, segments, segments, |u, v| {
+        (Vector3::new(u - 0.5, 0.0, 0.5 - v) * *size, Vector3::y())
+    });
+
+    Ok(finish_primitive(res))
+}
+
+// Axis-aligned cube with flat-shaded faces, each with the full [0, 1] UV range
+#[snoozy]
+pub async fn cube_mesh_snoozy(_ctx: Context, size: &f32) -> Result<TriangleMesh> {
+    let mut res = new_primitive();
+
+    // (normal, u axis, v axis), with `u x v = normal`
+    let faces = [
+        (Vector3::x(), -Vector3::z(), Vector3::y()),
+        (-Vector3::x(), Vector3::z(), Vector3::y()),
+        (Vector3::y(), Vector3::x(), -Vector3::z()),
+        (-Vector3::y(), Vector3::x(), Vector3::z()),
+        (Vector3::z(), Vector3::x(), Vector3::y()),
+        (-Vector3::z(), -Vector3::x(), Vector3::y()),
+    ];
+
+    for (normal, u_axis, v_axis) in faces.iter() {
+        push_grid(&mut res, 1, 1, |u, v| {
+            (
+                (normal + u_axis * (2.0 * u - 1.0) + v_axis * (2.0 * v - 1.0)) * (0.5 * *size),
+                *normal,
+            )
+        });
+    }
+
+    Ok(finish_primitive(res))
+}
+
+// Latitude-longitude sphere. U wraps around the Y axis, and V goes from pole to pole.
+#[snoozy]
+pub async fn uv_sphere_mesh_snoozy(
+    _ctx: Context,
+    radius: &f32,
+    segments: &u32,
+    rings: &u32,
+) -> Result<TriangleMesh> {
+    let mut res = new_primitive();
+
+    push_grid(&mut res, (*segments).max(3), (*rings).max(2), |u, v| {
+        let phi = u * 2.0 * PI;
+        let theta = v * PI;
+        let dir = Vector3::new(
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+            theta.sin() * phi.cos(),
+        );
+        (dir * *radius, dir)
+    });
+
+    Ok(finish_primitive(res))
+}
+
+// Subdivided icosahedron, which has a more even triangle distribution than `uv_sphere_mesh`.
+// Uses the same UV mapping as `uv_sphere_mesh`, with vertices split along the seam.
+#[snoozy]
+pub async fn ico_sphere_mesh_snoozy(
+    _ctx: Context,
+    radius: &f32,
+    subdivisions: &u32,
+) -> Result<TriangleMesh> {
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut dirs: Vec<Vector3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|(x, y, z)| Vector3::new(*x, *y, *z).normalize())
+    .collect();
+
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..(*subdivisions).min(8) {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                dirs.push((dirs[a as usize] + dirs[b as usize]).normalize());
+                dirs.len() as u32 - 1
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|[a, b, c]| {
+                let ab = midpoint(*a, *b);
+                let bc = midpoint(*b, *c);
+                let ca = midpoint(*c, *a);
+                vec![[*a, ab, ca], [*b, bc, ab], [*c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let uv_of = |dir: &Vector3| {
+        let u = dir.x.atan2(dir.z) / (2.0 * PI);
+        let u = if u < 0.0 { u + 1.0 } else { u };
+        let v = (-dir.y).max(-1.0).min(1.0).acos() / PI;
+        [u, 1.0 - v]
+    };
+
+    let mut res = new_primitive();
+    let uvs: Vec<[f32; 2]> = dirs.iter().map(uv_of).collect();
+    for (dir, uv) in dirs.iter().zip(uvs.iter()) {
+        push_vertex(&mut res, dir * *radius, *dir, *uv);
+    }
+
+    // Triangles crossing the seam get their own copies of the vertices on the low side of U.
+    // Poles have no meaningful U, so they get a copy with the average U of the other corners.
+    let mut seam_copies: HashMap<(u32, u32), u32> = HashMap::new();
+    let is_pole = |i: u32| dirs[i as usize].x.abs() + dirs[i as usize].z.abs() < 1e-6;
+
+    for tri in triangles.iter() {
+        let us: Vec<f32> = tri
+            .iter()
+            .filter(|i| !is_pole(**i))
+            .map(|i| uvs[*i as usize][0])
+            .collect();
+        let crosses_seam = us.iter().cloned().fold(0.0f32, f32::max)
+            - us.iter().cloned().fold(1.0f32, f32::min)
+            > 0.5;
+        let fixed_u = |u: f32| if crosses_seam && u < 0.5 { u + 1.0 } else { u };
+        let pole_u = us.iter().map(|u| fixed_u(*u)).sum::<f32>() / us.len().max(1) as f32;
+
+        for i in tri.iter() {
+            let uv = uvs[*i as usize];
+            let u = if is_pole(*i) { pole_u } else { fixed_u(uv[0]) };
+
+            let index = if u == uv[0] {
+                *i
+            } else {
+                let key = (*i, u.to_bits());
+                let dir = dirs[*i as usize];
+                *seam_copies
+                    .entry(key)
+                    .or_insert_with(|| push_vertex(&mut res, dir * *radius, dir, [u, uv[1]]))
+            };
+
+            res.indices.push(index);
+        }
+    }
+
+    Ok(finish_primitive(res))
+}
+
+// Capped cylinder along the Y axis
+#[snoozy]
+pub async fn cylinder_mesh_snoozy(
+    _ctx: Context,
+    radius: &f32,
+    height: &f32,
+    segments: &u32,
+) -> Result<TriangleMesh> {
+    let mut res = new_primitive();
+    let segments = (*segments).max(3);
+
+    push_grid(&mut res, segments, 1, |u, v| {
+        let phi = u * 2.0 * PI;
+        let normal = Vector3::new(phi.sin(), 0.0, phi.cos());
+        (
+            normal * *radius + Vector3::new(0.0, (v - 0.5) * *height, 0.0),
+            normal,
+        )
+    });
+
+    for side in [1.0f32, -1.0].iter() {
+        let normal = Vector3::new(0.0, *side, 0.0);
+        let center_pos = normal * (0.5 * *height);
+        let center = push_vertex(&mut res, center_pos, normal, [0.5, 0.5]);
+
+        let ring: Vec<u32> = (0..=segments)
+            .map(|i| {
+                let phi = i as f32 / segments as f32 * 2.0 * PI;
+                let (x, z) = (phi.sin(), phi.cos());
+                push_vertex(
+                    &mut res,
+                    center_pos + Vector3::new(x, 0.0, z) * *radius,
+                    normal,
+                    [0.5 + 0.5 * x, 0.5 + 0.5 * z * *side],
+                )
+            })
+            .collect();
+
+        for i in 0..segments as usize {
+            if *side > 0.0 {
+                res.indices
+                    .extend_from_slice(&[center, ring[i], ring[i + 1]]);
+            } else {
+                res.indices
+                    .extend_from_slice(&[center, ring[i + 1], ring[i]]);
+            }
+        }
+    }
+
+    Ok(finish_primitive(res))
+}
+
+// Torus around the Y axis. U goes around the Y axis, and V around the tube.
+#[snoozy]
+pub async fn torus_mesh_snoozy(
+    _ctx: Context,
+    major_radius: &f32,
+    minor_radius: &f32,
+    major_segments: &u32,
+    minor_segments: &u32,
+) -> Result<TriangleMesh> {
+    let mut res = new_primitive();
+
+    push_grid(
+        &mut res,
+        (*major_segments).max(3),
+        (*minor_segments).max(3),
+        |u, v| {
+            let phi = u * 2.0 * PI;
+            let theta = v * 2.0 * PI;
+            let center = Vector3::new(phi.sin(), 0.0, phi.cos()) * *major_radius;
+            let normal = Vector3::new(
+                theta.cos() * phi.sin(),
+                theta.sin(),
+                theta.cos() * phi.cos(),
+            );
+            (center + normal * *minor_radius, normal)
+        },
+    );
+
+    Ok(finish_primitive(res))
+}
+
+// Single triangle covering the [-1, 1] square in XY, facing +Z, with UVs
+// in [0, 1] across the square. Meant for shaders which output positions as-is.
+#[snoozy]
+pub async fn fullscreen_triangle_mesh_snoozy(_ctx: Context) -> Result<TriangleMesh> {
+    let mut res = new_primitive();
+
+    for (x, y) in [(-1.0f32, -1.0f32), (3.0, -1.0), (-1.0, 3.0)].iter() {
+        let index = push_vertex(
+            &mut res,
+            Vector3::new(*x, *y, 0.0),
+            Vector3::z(),
+            [0.5 * x + 0.5, 0.5 - 0.5 * y],
+        );
+        res.indices.push(index);
+    }
+
+    Ok(finish_primitive(res))
+}
@@ -0,0 +1,375 @@
+use crate::{CameraMatrices, Matrix4, Vector2};
+
+// Projections use reverse-Z, with depth going from 1 at the near plane to 0 at the far one,
+// or towards 0 at infinity if there isn't one. View space looks down -Z, with +Y up.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum CameraProjection {
+    Perspective {
+        // Vertical, in degrees
+        fov_y: f32,
+        aspect: f32,
+        near: f32,
+        // Infinite if not specified
+        far: Option<f32>,
+    },
+    // Perspective with arbitrary frustum extents, given at the near plane.
+    // Used for tiled rendering, stereo and lens shift.
+    OffAxis {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: Option<f32>,
+    },
+    // Extents in view-space units. `near` may be negative to include geometry behind the camera.
+    Orthographic {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl Default for CameraProjection {
+    fn default() -> Self {
+        Self::perspective(45.0, 1.6, 0.1)
+    }
+}
+
+impl CameraProjection {
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32) -> Self {
+        CameraProjection::Perspective {
+            fov_y,
+            aspect,
+            near,
+            far: None,
+        }
+    }
+
+    // Symmetric orthographic projection `height` units tall
+    pub fn orthographic(height: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let half_height = 0.5 * height;
+        let half_width = half_height * aspect;
+        CameraProjection::Orthographic {
+            left: -half_width,
+            right: half_width,
+            bottom: -half_height,
+            top: half_height,
+            near,
+            far,
+        }
+    }
+
+    // Pinhole camera with a sensor of `sensor_size` (width, height) and `focal_length`,
+    // both in millimeters. `lens_shift` moves the frustum by fractions of the sensor size.
+    pub fn physical(
+        sensor_size: Vector2,
+        focal_length: f32,
+        lens_shift: Vector2,
+        near: f32,
+    ) -> Self {
+        let scale = near / focal_length;
+        let half_size = sensor_size * (0.5 * scale);
+        let shift = sensor_size.component_mul(&lens_shift) * scale;
+
+        CameraProjection::OffAxis {
+            left: shift.x - half_size.x,
+            right: shift.x + half_size.x,
+            bottom: shift.y - half_size.y,
+            top: shift.y + half_size.y,
+            near,
+            far: None,
+        }
+    }
+
+    // Sets the far plane of perspective projections; orthographic ones always have one
+    pub fn with_far(mut self, far_dist: Option<f32>) -> Self {
+        match &mut self {
+            CameraProjection::Perspective { far, .. } | CameraProjection::OffAxis { far, .. } => {
+                *far = far_dist
+            }
+            CameraProjection::Orthographic { far, .. } => {
+                if let Some(far_dist) = far_dist {
+                    *far = far_dist;
+                }
+            }
+        }
+        self
+    }
+
+    pub fn near(&self) -> f32 {
+        match *self {
+            CameraProjection::Perspective { near, .. }
+            | CameraProjection::OffAxis { near, .. }
+            | CameraProjection::Orthographic { near, .. } => near,
+        }
+    }
+
+    // Moves the near plane. The extents of off-axis frusta are given at the near plane,
+    // so they're scaled along with it, keeping the field of view.
+    pub fn set_near(&mut self, new_near: f32) {
+        match self {
+            CameraProjection::Perspective { near, .. }
+            | CameraProjection::Orthographic { near, .. } => *near = new_near,
+            CameraProjection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                ..
+            } => {
+                let scale = new_near / *near;
+                *left *= scale;
+                *right *= scale;
+                *bottom *= scale;
+                *top *= scale;
+                *near = new_near;
+            }
+        }
+    }
+
+    // Vertical field of view in degrees; zero for orthographic projections
+    pub fn fov_y(&self) -> f32 {
+        if let CameraProjection::Perspective { fov_y, .. } = *self {
+            return fov_y;
+        }
+
+        if self.is_orthographic() {
+            return 0.0;
+        }
+
+        let [_, _, bottom, top] = self.extents();
+        let near = self.near();
+        (top.atan2(near) - bottom.atan2(near)).to_degrees()
+    }
+
+    // Changes the vertical field of view, keeping the aspect ratio.
+    // Off-center frusta are scaled around their center. Orthographic projections don't have one.
+    pub fn set_fov_y(&mut self, new_fov_y: f32) {
+        let scale = (0.5 * new_fov_y.to_radians()).tan() / (0.5 * self.fov_y().to_radians()).tan();
+
+        match self {
+            CameraProjection::Perspective { fov_y, .. } => *fov_y = new_fov_y,
+            CameraProjection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+                ..
+            } => {
+                let center_x = 0.5 * (*left + *right);
+                let center_y = 0.5 * (*bottom + *top);
+                *left = center_x + (*left - center_x) * scale;
+                *right = center_x + (*right - center_x) * scale;
+                *bottom = center_y + (*bottom - center_y) * scale;
+                *top = center_y + (*top - center_y) * scale;
+            }
+            CameraProjection::Orthographic { .. } => {}
+        }
+    }
+
+    // Width over height
+    pub fn aspect(&self) -> f32 {
+        if let CameraProjection::Perspective { aspect, .. } = *self {
+            return aspect;
+        }
+
+        let [left, right, bottom, top] = self.extents();
+        (right - left) / (top - bottom)
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        match self {
+            CameraProjection::Orthographic { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Left, right, bottom and top; at the near plane for perspective projections
+    pub fn extents(&self) -> [f32; 4] {
+        match *self {
+            CameraProjection::Perspective {
+                fov_y,
+                aspect,
+                near,
+                ..
+            } => {
+                let top = near * (0.5 * fov_y.to_radians()).tan();
+                let right = top * aspect;
+                [-right, right, -top, top]
+            }
+            CameraProjection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+                ..
+            }
+            | CameraProjection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+                ..
+            } => [left, right, bottom, top],
+        }
+    }
+
+    fn far(&self) -> Option<f32> {
+        match *self {
+            CameraProjection::Perspective { far, .. } | CameraProjection::OffAxis { far, .. } => {
+                far
+            }
+            CameraProjection::Orthographic { far, .. } => Some(far),
+        }
+    }
+
+    // Width and height of the visible region at `distance` from the camera
+    pub fn view_size_at(&self, distance: f32) -> Vector2 {
+        let [left, right, bottom, top] = self.extents();
+        let size = Vector2::new(right - left, top - bottom);
+
+        if self.is_orthographic() {
+            size
+        } else {
+            size * (distance / self.near())
+        }
+    }
+
+    // Changes the horizontal extent to match `aspect`, keeping the vertical one.
+    // Off-center frusta are scaled around their center.
+    pub fn set_aspect(&mut self, new_aspect: f32) {
+        if let CameraProjection::Perspective { aspect, .. } = self {
+            *aspect = new_aspect;
+            return;
+        }
+
+        let [l, r, b, t] = self.extents();
+        let center = 0.5 * (l + r);
+        let half_width = 0.5 * (t - b) * new_aspect;
+
+        match self {
+            CameraProjection::OffAxis { left, right, .. }
+            | CameraProjection::Orthographic { left, right, .. } => {
+                *left = center - half_width;
+                *right = center + half_width;
+            }
+            CameraProjection::Perspective { .. } => unreachable!(),
+        }
+    }
+
+    // Sub-frustum covering the `min`..`max` rectangle of the viewport, with coordinates
+    // in [0, 1], and (0, 0) at the top-left corner, like pixel coordinates.
+    pub fn tile(&self, min: Vector2, max: Vector2) -> Self {
+        let [l, r, b, t] = self.extents();
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let left = lerp(l, r, min.x);
+        let right = lerp(l, r, max.x);
+        let top = lerp(t, b, min.y);
+        let bottom = lerp(t, b, max.y);
+
+        match *self {
+            CameraProjection::Perspective { near, far, .. }
+            | CameraProjection::OffAxis { near, far, .. } => CameraProjection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                far,
+            },
+            CameraProjection::Orthographic { near, far, .. } => CameraProjection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                far,
+            },
+        }
+    }
+
+    // Returns `(view_to_clip, clip_to_view)`. The inverses are built directly
+    // rather than via `try_inverse`, which loses precision with reverse-Z.
+    pub fn calc_clip_matrices(&self) -> (Matrix4, Matrix4) {
+        let [l, r, b, t] = self.extents();
+        let near = self.near();
+
+        if self.is_orthographic() {
+            let far = self.far().unwrap();
+
+            let sx = 2.0 / (r - l);
+            let sy = 2.0 / (t - b);
+            let cx = -(r + l) / (r - l);
+            let cy = -(t + b) / (t - b);
+            let dz = 1.0 / (far - near);
+
+            let mut view_to_clip = Matrix4::zeros();
+            view_to_clip.m11 = sx;
+            view_to_clip.m14 = cx;
+            view_to_clip.m22 = sy;
+            view_to_clip.m24 = cy;
+            view_to_clip.m33 = dz;
+            view_to_clip.m34 = far * dz;
+            view_to_clip.m44 = 1.0;
+
+            let mut clip_to_view = Matrix4::zeros();
+            clip_to_view.m11 = 1.0 / sx;
+            clip_to_view.m14 = -cx / sx;
+            clip_to_view.m22 = 1.0 / sy;
+            clip_to_view.m24 = -cy / sy;
+            clip_to_view.m33 = far - near;
+            clip_to_view.m34 = -far;
+            clip_to_view.m44 = 1.0;
+
+            (view_to_clip, clip_to_view)
+        } else {
+            let sx = 2.0 * near / (r - l);
+            let sy = 2.0 * near / (t - b);
+            let cx = (r + l) / (r - l);
+            let cy = (t + b) / (t - b);
+
+            // Depth is `dz_b / dist - dz_a`; 1 at `near`, and 0 at `far`
+            let (dz_a, dz_b) = match self.far() {
+                Some(far) => (near / (far - near), near * far / (far - near)),
+                None => (0.0, near),
+            };
+
+            let mut view_to_clip = Matrix4::zeros();
+            view_to_clip.m11 = sx;
+            view_to_clip.m13 = cx;
+            view_to_clip.m22 = sy;
+            view_to_clip.m23 = cy;
+            view_to_clip.m33 = dz_a;
+            view_to_clip.m34 = dz_b;
+            view_to_clip.m43 = -1.0;
+
+            let mut clip_to_view = Matrix4::zeros();
+            clip_to_view.m11 = 1.0 / sx;
+            clip_to_view.m14 = cx / sx;
+            clip_to_view.m22 = 1.0 / sy;
+            clip_to_view.m24 = cy / sy;
+            clip_to_view.m34 = -1.0;
+            clip_to_view.m43 = 1.0 / dz_b;
+            clip_to_view.m44 = dz_a / dz_b;
+
+            (view_to_clip, clip_to_view)
+        }
+    }
+}
+
+impl CameraMatrices {
+    // Replaces the projection, keeping the view transforms; e.g. to render
+    // a `CameraProjection::tile` of a camera's view.
+    pub fn with_projection(mut self, projection: &CameraProjection) -> Self {
+        let (view_to_clip, clip_to_view) = projection.calc_clip_matrices();
+        self.view_to_clip = view_to_clip;
+        self.clip_to_view = clip_to_view;
+        self
+    }
+}
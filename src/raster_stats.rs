@@ -0,0 +1,39 @@
+use std::sync::Mutex;
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct RasterStats {
+    pub drawn_instances: u32,
+    pub culled_instances: u32,
+}
+
+// Called by `raster_tex` once per pass
+pub fn report_raster_pass(drawn_instances: u32, culled_instances: u32) {
+    let mut stats = RASTER_STATS.lock().unwrap();
+    let frame = stats.frame.get_or_insert_with(Default::default);
+    frame.drawn_instances += drawn_instances;
+    frame.culled_instances += culled_instances;
+}
+
+// Raster passes whose inputs haven't changed are not executed again,
+// so the stats of the last frame which had any are kept around.
+pub fn get_stats() -> RasterStats {
+    let stats = RASTER_STATS.lock().unwrap();
+    stats.frame.unwrap_or(stats.latest)
+}
+
+pub fn end_frame() {
+    let mut stats = RASTER_STATS.lock().unwrap();
+    if let Some(frame) = stats.frame.take() {
+        stats.latest = frame;
+    }
+}
+
+#[derive(Default)]
+struct RasterStatsState {
+    frame: Option<RasterStats>,
+    latest: RasterStats,
+}
+
+lazy_static! {
+    static ref RASTER_STATS: Mutex<RasterStatsState> = { Mutex::new(Default::default()) };
+}
@@ -0,0 +1,683 @@
+use crate::render_pass_list;
+use crate::{FrameState, RenderPassList, ViewConstants};
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use snoozy::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use vk_sync::AccessType;
+
+// Resources which a `RenderPass` reads and writes, declared by name. The same name
+// must refer to the same resource in all passes of a `RenderGraph`.
+#[derive(Default)]
+pub struct RenderPassResources {
+    reads: Vec<(String, AccessType)>,
+    writes: Vec<(String, AccessType)>,
+}
+
+impl RenderPassResources {
+    pub fn read(&mut self, name: &str, access: AccessType) -> &mut Self {
+        self.reads.push((name.to_owned(), access));
+        self
+    }
+
+    pub fn write(&mut self, name: &str, access: AccessType) -> &mut Self {
+        self.writes.push((name.to_owned(), access));
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.reads.is_empty() && self.writes.is_empty()
+    }
+
+    fn writes_resource(&self, name: &str) -> bool {
+        self.writes.iter().any(|(n, _)| n == name)
+    }
+
+    // Every resource with all of its accesses by this pass
+    fn accesses(&self) -> Vec<(&str, Vec<AccessType>)> {
+        let mut res: Vec<(&str, Vec<AccessType>)> = Vec::new();
+
+        for (name, access) in self.reads.iter().chain(self.writes.iter()) {
+            match res.iter_mut().find(|(n, _)| n == name) {
+                Some((_, accesses)) => {
+                    if !accesses.contains(access) {
+                        accesses.push(*access);
+                    }
+                }
+                None => res.push((name, vec![*access])),
+            }
+        }
+
+        res
+    }
+}
+
+// Transition of a resource between the accesses of consecutive passes.
+// Runs of passes which only read a resource share a single barrier.
+#[derive(Clone, Debug)]
+pub struct RenderGraphBarrier {
+    pub resource: String,
+    pub prev_accesses: Vec<AccessType>,
+    pub next_accesses: Vec<AccessType>,
+    // Set for the first write in the graph unless the same pass also reads the resource,
+    // as the previous contents are unused
+    pub discard: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderGraphPass {
+    // Index into the `RenderPassList` the graph was compiled from
+    pub pass_idx: usize,
+    pub name: String,
+    // To be recorded before the pass runs
+    pub barriers: Vec<RenderGraphBarrier>,
+}
+
+#[derive(Clone, Debug)]
+struct RenderGraphEdge {
+    // The pass which runs first
+    from: usize,
+    to: usize,
+    resource: String,
+}
+
+// Orders the passes of a `RenderPassList` by the resources they declare via
+// `RenderPass::declare_resources`, and culls those which don't contribute to `outputs`.
+// Passes keep their list order where their dependencies allow it. Passes which don't
+// declare any resources are never culled, and run in list order relative to each other.
+// Without any `outputs`, nothing is culled.
+//
+//   let graph = RenderGraph::compile(&passes, &["lighting"])?;
+//   graph.execute(&mut passes, &view_constants, frame_state, frame_idx);
+//
+// NOTE: The merged barriers are only applied by passes which record their own Vulkan
+// commands, and call `record_barriers`. `compute_tex`, `compute_buffer` and `raster_tex` still
+// record their own Nothing->Write and Write->AnyShaderRead barriers. `prepare_frame` only
+// builds snoozy refs, and the commands of those ops get recorded later, in the order the
+// snoozy graph is evaluated in, which the render graph doesn't control. The GUI says as much
+// next to the barriers it lists.
+#[derive(Clone, Debug)]
+pub struct RenderGraph {
+    passes: Vec<RenderGraphPass>,
+    culled: Vec<RenderGraphPass>,
+    edges: Vec<RenderGraphEdge>,
+}
+
+lazy_static! {
+    static ref LAST_COMPILED_GRAPH: Mutex<Option<RenderGraph>> = { Mutex::new(None) };
+}
+
+fn pass_name(passes: &RenderPassList, pass_idx: usize) -> String {
+    format!("{}: {}", pass_idx, passes[pass_idx].name())
+}
+
+impl RenderGraph {
+    pub fn compile(passes: &RenderPassList, outputs: &[&str]) -> Result<Self> {
+        let resources: Vec<RenderPassResources> = passes
+            .iter()
+            .map(|pass| {
+                let mut resources = RenderPassResources::default();
+                pass.declare_resources(&mut resources);
+                resources
+            })
+            .collect();
+
+        let edges = Self::find_dependencies(&resources);
+        let order = Self::sort_passes(passes.len(), &edges).map_err(|cycle| {
+            let names: Vec<String> = cycle.iter().map(|idx| pass_name(passes, *idx)).collect();
+            format_err!("Render pass dependency cycle between {}", names.join(", "))
+        })?;
+
+        let live = Self::find_live_passes(&resources, &edges, outputs);
+        let (live_order, culled_order): (Vec<usize>, Vec<usize>) =
+            order.into_iter().partition(|idx| live[*idx]);
+
+        let barriers = Self::place_barriers(&live_order, &resources);
+        let passes_in_order = live_order
+            .iter()
+            .zip(barriers.into_iter())
+            .map(|(idx, barriers)| RenderGraphPass {
+                pass_idx: *idx,
+                name: pass_name(passes, *idx),
+                barriers,
+            })
+            .collect();
+
+        let culled = culled_order
+            .iter()
+            .map(|idx| RenderGraphPass {
+                pass_idx: *idx,
+                name: pass_name(passes, *idx),
+                barriers: Vec::new(),
+            })
+            .collect();
+
+        let graph = RenderGraph {
+            passes: passes_in_order,
+            culled,
+            edges,
+        };
+
+        *LAST_COMPILED_GRAPH.lock().unwrap() = Some(graph.clone());
+        Ok(graph)
+    }
+
+    // Walks back from the writers of `outputs`, keeping everything they depend on
+    fn find_live_passes(
+        resources: &[RenderPassResources],
+        edges: &[RenderGraphEdge],
+        outputs: &[&str],
+    ) -> Vec<bool> {
+        let mut live: Vec<bool> = resources
+            .iter()
+            .map(|r| r.is_empty() || outputs.is_empty())
+            .collect();
+        let mut stack: Vec<usize> = (0..resources.len())
+            .filter(|idx| outputs.iter().any(|o| resources[*idx].writes_resource(o)))
+            .collect();
+
+        while let Some(idx) = stack.pop() {
+            if !live[idx] {
+                live[idx] = true;
+                stack.extend(edges.iter().filter(|e| e.to == idx).map(|e| e.from));
+            }
+        }
+
+        live
+    }
+
+    // Reads depend on the last preceding write in list order, or on the first write
+    // if none precede them. Writes depend on the write before them, and on the reads
+    // of that write; reads which precede all writes count as reads of the first one.
+    fn find_dependencies(resources: &[RenderPassResources]) -> Vec<RenderGraphEdge> {
+        let mut edges: Vec<RenderGraphEdge> = Vec::new();
+        let mut add_edge = |from: usize, to: usize, resource: &str| {
+            let exists = edges
+                .iter()
+                .any(|e| e.from == from && e.to == to && e.resource == resource);
+            if from != to && !exists {
+                edges.push(RenderGraphEdge {
+                    from,
+                    to,
+                    resource: resource.to_owned(),
+                });
+            }
+        };
+
+        let mut names: Vec<&str> = Vec::new();
+        for r in resources {
+            for (name, _) in r.reads.iter().chain(r.writes.iter()) {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+
+        for name in names {
+            let first_writer = resources.iter().position(|r| r.writes_resource(name));
+            let mut last_writer: Option<usize> = None;
+            let mut readers_since_write: Vec<usize> = Vec::new();
+            // Readers listed before the first writer, which run after it
+            let mut readers_of_first_write: Vec<usize> = Vec::new();
+
+            for (idx, r) in resources.iter().enumerate() {
+                let reads = r.reads.iter().any(|(n, _)| n == name);
+                let writes = r.writes_resource(name);
+
+                if reads {
+                    if let Some(writer) = last_writer.or(first_writer) {
+                        add_edge(writer, idx, name);
+                    }
+                }
+
+                if writes {
+                    if let Some(writer) = last_writer {
+                        add_edge(writer, idx, name);
+                        // This is the second write at the earliest, which must wait for
+                        // all reads of the first one
+                        readers_since_write.append(&mut readers_of_first_write);
+                    }
+                    for reader in readers_since_write.drain(..) {
+                        add_edge(reader, idx, name);
+                    }
+                    last_writer = Some(idx);
+                } else if reads && last_writer.is_some() {
+                    readers_since_write.push(idx);
+                } else if reads {
+                    readers_of_first_write.push(idx);
+                }
+            }
+        }
+
+        edges
+    }
+
+    // Topological sort which always picks the ready pass earliest in the list.
+    // On failure, returns the passes which are part of, or wait on a cycle.
+    fn sort_passes(
+        pass_count: usize,
+        edges: &[RenderGraphEdge],
+    ) -> std::result::Result<Vec<usize>, Vec<usize>> {
+        let mut in_degree = vec![0usize; pass_count];
+        for e in edges {
+            in_degree[e.to] += 1;
+        }
+
+        let mut order = Vec::with_capacity(pass_count);
+        let mut done = vec![false; pass_count];
+
+        while order.len() < pass_count {
+            let next = (0..pass_count).find(|idx| !done[*idx] && 0 == in_degree[*idx]);
+            let next = match next {
+                Some(next) => next,
+                None => return Err((0..pass_count).filter(|idx| !done[*idx]).collect()),
+            };
+
+            done[next] = true;
+            order.push(next);
+            for e in edges.iter().filter(|e| e.from == next) {
+                in_degree[e.to] -= 1;
+            }
+        }
+
+        Ok(order)
+    }
+
+    // Splits the accesses to every resource into phases: a single writing pass,
+    // or a run of reading ones. A barrier precedes the first pass of each phase,
+    // and waits for all accesses of the previous one.
+    fn place_barriers(
+        order: &[usize],
+        resources: &[RenderPassResources],
+    ) -> Vec<Vec<RenderGraphBarrier>> {
+        struct Phase {
+            first_pos: usize,
+            is_write: bool,
+            // Whether the pass also reads the resource, in which case it's written in place
+            is_read: bool,
+            accesses: Vec<AccessType>,
+        }
+
+        let mut phases: HashMap<&str, Vec<Phase>> = HashMap::new();
+        let mut resource_order: Vec<&str> = Vec::new();
+
+        for (pos, idx) in order.iter().enumerate() {
+            let r = &resources[*idx];
+
+            for (name, accesses) in r.accesses() {
+                let is_write = r.writes_resource(name);
+                let is_read = r.reads.iter().any(|(n, _)| n == name);
+                let resource_phases = phases.entry(name).or_insert_with(|| {
+                    resource_order.push(name);
+                    Vec::new()
+                });
+
+                match resource_phases.last_mut() {
+                    Some(phase) if !phase.is_write && !is_write => {
+                        for access in accesses {
+                            if !phase.accesses.contains(&access) {
+                                phase.accesses.push(access);
+                            }
+                        }
+                    }
+                    _ => resource_phases.push(Phase {
+                        first_pos: pos,
+                        is_write,
+                        is_read,
+                        accesses,
+                    }),
+                }
+            }
+        }
+
+        let mut barriers: Vec<Vec<RenderGraphBarrier>> = vec![Vec::new(); order.len()];
+
+        for name in resource_order {
+            let mut prev_accesses = vec![AccessType::Nothing];
+
+            for (i, phase) in phases[name].iter().enumerate() {
+                barriers[phase.first_pos].push(RenderGraphBarrier {
+                    resource: name.to_owned(),
+                    prev_accesses: std::mem::replace(&mut prev_accesses, phase.accesses.clone()),
+                    next_accesses: phase.accesses.clone(),
+                    discard: 0 == i && phase.is_write && !phase.is_read,
+                });
+            }
+        }
+
+        barriers
+    }
+
+    // Live passes in execution order
+    pub fn passes(&self) -> &[RenderGraphPass] {
+        &self.passes
+    }
+
+    // Passes whose output doesn't reach any of the graph's outputs
+    pub fn culled_passes(&self) -> &[RenderGraphPass] {
+        &self.culled
+    }
+
+    // Runs the enabled passes in order; see `RenderPassListExt::prepare_frame`
+    pub fn execute(
+        &self,
+        passes: &mut RenderPassList,
+        view_constants: &ViewConstants,
+        frame_state: &FrameState,
+        frame_idx: u32,
+    ) {
+        let enabled = render_pass_list::enabled_flags(passes);
+        for pass in self.passes.iter() {
+            if enabled[pass.pass_idx] {
+                passes[pass.pass_idx].prepare_frame(view_constants, frame_state, frame_idx);
+            }
+        }
+
+        // After running, as reordering invalidates the graph's pass indices
+        render_pass_list::draw_frame_gui(passes, frame_state);
+    }
+
+    // Records the barriers of the pass at `order_idx` in `passes()` as a single
+    // pipeline barrier, for passes which record their own commands.
+    // `get_image` maps resource names to images and their formats; unknown resources
+    // are skipped. Barriers cover all mips and layers of the images.
+    pub fn record_barriers(
+        &self,
+        order_idx: usize,
+        device: &Device,
+        cb: vk::CommandBuffer,
+        get_image: impl Fn(&str) -> Option<(vk::Image, vk::Format)>,
+    ) {
+        let image_barriers: Vec<vk_sync::ImageBarrier> = self.passes[order_idx]
+            .barriers
+            .iter()
+            .filter_map(|barrier| {
+                let (image, format) = get_image(&barrier.resource)?;
+
+                Some(vk_sync::ImageBarrier {
+                    previous_accesses: &barrier.prev_accesses,
+                    next_accesses: &barrier.next_accesses,
+                    previous_layout: vk_sync::ImageLayout::Optimal,
+                    next_layout: vk_sync::ImageLayout::Optimal,
+                    discard_contents: barrier.discard,
+                    src_queue_family_index: 0,
+                    dst_queue_family_index: 0,
+                    image,
+                    range: vk::ImageSubresourceRange {
+                        aspect_mask: image_aspect_mask(format),
+                        base_mip_level: 0,
+                        level_count: vk::REMAINING_MIP_LEVELS,
+                        base_array_layer: 0,
+                        layer_count: vk::REMAINING_ARRAY_LAYERS,
+                    },
+                })
+            })
+            .collect();
+
+        if !image_barriers.is_empty() {
+            vk_sync::cmd::pipeline_barrier(device.fp_v1_0(), cb, None, &[], &image_barriers);
+        }
+    }
+
+    // Passes as nodes, and dependencies as edges labeled with resource names,
+    // in the format of the snoozy graph dump.
+    pub fn to_dot(&self) -> String {
+        use petgraph::Graph;
+
+        let mut graph = Graph::<String, String>::new();
+        let mut node_indices = HashMap::new();
+
+        for (order_idx, pass) in self.passes.iter().enumerate() {
+            let label = format!("#{} {}", order_idx, pass.name);
+            node_indices.insert(pass.pass_idx, graph.add_node(label));
+        }
+
+        for e in self.edges.iter() {
+            if let (Some(from), Some(to)) = (node_indices.get(&e.from), node_indices.get(&e.to)) {
+                graph.add_edge(*to, *from, e.resource.clone());
+            }
+        }
+
+        format!("{}", crate::dot::Dot::new(&graph, Some("rankdir = BT")))
+    }
+}
+
+fn image_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+// The most recently compiled graph, for the dump next to frame.dot
+pub(crate) fn last_compiled_graph() -> Option<RenderGraph> {
+    LAST_COMPILED_GRAPH.lock().unwrap().clone()
+}
+
+pub(crate) fn draw_gui(ui: &imgui::Ui) {
+    let graph = LAST_COMPILED_GRAPH.lock().unwrap();
+    let graph = match graph.as_ref() {
+        Some(graph) => graph,
+        None => {
+            ui.text("No render graph compiled");
+            return;
+        }
+    };
+
+    ui.text("Barriers are only recorded by passes calling record_barriers;");
+    ui.text("compute_tex, compute_buffer and raster_tex record their own.");
+    ui.spacing();
+
+    for (order_idx, pass) in graph.passes.iter().enumerate() {
+        let deps: Vec<String> = graph
+            .edges
+            .iter()
+            .filter(|e| e.to == pass.pass_idx)
+            .map(|e| format!("{} ({})", e.from, e.resource))
+            .collect();
+
+        ui.text(format!("#{} {}", order_idx, pass.name));
+        if !deps.is_empty() {
+            ui.text(format!("    after {}", deps.join(", ")));
+        }
+        if !pass.barriers.is_empty() {
+            let resources: Vec<&str> = pass.barriers.iter().map(|b| b.resource.as_str()).collect();
+            ui.text(format!(
+                "    barriers (record_barriers): {}",
+                resources.join(", ")
+            ));
+        }
+    }
+
+    if !graph.culled.is_empty() {
+        ui.spacing();
+        ui.text("Culled:");
+        for pass in graph.culled.iter() {
+            ui.text(format!("    {}", pass.name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(reads: &[&str], writes: &[&str]) -> RenderPassResources {
+        let mut res = RenderPassResources::default();
+        for name in reads {
+            res.read(
+                name,
+                AccessType::ComputeShaderReadSampledImageOrUniformTexelBuffer,
+            );
+        }
+        for name in writes {
+            res.write(name, AccessType::ComputeShaderWrite);
+        }
+        res
+    }
+
+    fn edge(from: usize, to: usize) -> RenderGraphEdge {
+        RenderGraphEdge {
+            from,
+            to,
+            resource: "a".to_owned(),
+        }
+    }
+
+    fn edge_pairs(edges: &[RenderGraphEdge]) -> Vec<(usize, usize)> {
+        let mut res: Vec<(usize, usize)> = edges.iter().map(|e| (e.from, e.to)).collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn reads_depend_on_the_preceding_write() {
+        let edges = RenderGraph::find_dependencies(&[
+            pass(&[], &["a"]),
+            pass(&["a"], &[]),
+            pass(&[], &["a"]),
+            pass(&["a"], &[]),
+        ]);
+
+        assert_eq!(edge_pairs(&edges), vec![(0, 1), (0, 2), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn reads_before_the_first_write_run_between_it_and_the_second_one() {
+        let edges = RenderGraph::find_dependencies(&[
+            pass(&["a"], &[]),
+            pass(&[], &["a"]),
+            pass(&[], &["a"]),
+        ]);
+
+        assert_eq!(edge_pairs(&edges), vec![(0, 2), (1, 0), (1, 2)]);
+        assert_eq!(RenderGraph::sort_passes(3, &edges).unwrap(), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn read_write_passes_depend_on_the_previous_write_only() {
+        let edges = RenderGraph::find_dependencies(&[
+            pass(&[], &["a"]),
+            pass(&["a"], &["a"]),
+            pass(&["a"], &[]),
+        ]);
+
+        assert_eq!(edge_pairs(&edges), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn sort_keeps_list_order_where_possible() {
+        assert_eq!(RenderGraph::sort_passes(3, &[]).unwrap(), vec![0, 1, 2]);
+        assert_eq!(
+            RenderGraph::sort_passes(4, &[edge(2, 1)]).unwrap(),
+            vec![0, 2, 1, 3]
+        );
+    }
+
+    #[test]
+    fn sort_reports_cycles() {
+        let cycle = RenderGraph::sort_passes(4, &[edge(1, 2), edge(2, 1), edge(2, 3)]);
+        assert_eq!(cycle.unwrap_err(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn passes_not_contributing_to_outputs_are_culled() {
+        let resources = [
+            pass(&[], &["a"]),
+            pass(&[], &["b"]),
+            pass(&["a"], &["c"]),
+            pass(&[], &[]),
+        ];
+        let edges = RenderGraph::find_dependencies(&resources);
+
+        assert_eq!(
+            RenderGraph::find_live_passes(&resources, &edges, &["c"]),
+            vec![true, false, true, true]
+        );
+        assert_eq!(
+            RenderGraph::find_live_passes(&resources, &edges, &[]),
+            vec![true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn barriers_merge_consecutive_reads() {
+        let mut fragment_read = RenderPassResources::default();
+        fragment_read.read(
+            "a",
+            AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer,
+        );
+
+        let resources = [
+            pass(&[], &["a"]),
+            pass(&["a"], &[]),
+            fragment_read,
+            pass(&[], &["a"]),
+        ];
+        let barriers = RenderGraph::place_barriers(&[0, 1, 2, 3], &resources);
+
+        let compute_read = AccessType::ComputeShaderReadSampledImageOrUniformTexelBuffer;
+        let fragment_read = AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer;
+
+        assert_eq!(barriers[0].len(), 1);
+        assert_eq!(barriers[0][0].prev_accesses, vec![AccessType::Nothing]);
+        assert_eq!(
+            barriers[0][0].next_accesses,
+            vec![AccessType::ComputeShaderWrite]
+        );
+        assert!(barriers[0][0].discard);
+
+        assert_eq!(barriers[1].len(), 1);
+        assert_eq!(
+            barriers[1][0].prev_accesses,
+            vec![AccessType::ComputeShaderWrite]
+        );
+        assert_eq!(
+            barriers[1][0].next_accesses,
+            vec![compute_read, fragment_read]
+        );
+        assert!(!barriers[1][0].discard);
+
+        assert!(barriers[2].is_empty());
+
+        assert_eq!(barriers[3].len(), 1);
+        assert_eq!(
+            barriers[3][0].prev_accesses,
+            vec![compute_read, fragment_read]
+        );
+        assert_eq!(
+            barriers[3][0].next_accesses,
+            vec![AccessType::ComputeShaderWrite]
+        );
+        assert!(!barriers[3][0].discard);
+    }
+
+    #[test]
+    fn first_write_keeps_contents_when_also_read() {
+        let resources = [pass(&["a"], &["a"]), pass(&["a"], &[])];
+        let barriers = RenderGraph::place_barriers(&[0, 1], &resources);
+
+        assert_eq!(barriers[0].len(), 1);
+        assert_eq!(barriers[0][0].prev_accesses, vec![AccessType::Nothing]);
+        assert_eq!(
+            barriers[0][0].next_accesses,
+            vec![
+                AccessType::ComputeShaderReadSampledImageOrUniformTexelBuffer,
+                AccessType::ComputeShaderWrite
+            ]
+        );
+        assert!(!barriers[0][0].discard);
+
+        assert_eq!(barriers[1].len(), 1);
+        assert!(!barriers[1][0].discard);
+    }
+}
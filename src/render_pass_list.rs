@@ -0,0 +1,127 @@
+use crate::{FrameState, RenderPassList, ViewConstants};
+use imgui::im_str;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Whether passes are enabled, by display name. Kept globally rather than in the passes,
+// so that the setting survives the pass list being rebuilt. Passes sharing a name
+// are told apart by their order, so they should be given unique ones via `add_named`.
+lazy_static! {
+    static ref RENDER_PASS_ENABLED: Mutex<HashMap<String, bool>> = { Mutex::new(HashMap::new()) };
+}
+
+// `RenderPass::name`, with a suffix on passes sharing a name with an earlier one
+fn display_names(passes: &RenderPassList) -> Vec<String> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+
+    passes
+        .iter()
+        .map(|pass| {
+            let name = pass.name();
+            let count = counts.entry(name.clone()).or_insert(0);
+            *count += 1;
+
+            if *count > 1 {
+                format!("{} ({})", name, count)
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+// Whether each pass of the list is enabled; passes are, until unchecked in the GUI
+pub(crate) fn enabled_flags(passes: &RenderPassList) -> Vec<bool> {
+    let enabled = RENDER_PASS_ENABLED.lock().unwrap();
+    display_names(passes)
+        .iter()
+        .map(|name| enabled.get(name).cloned().unwrap_or(true))
+        .collect()
+}
+
+// Lists the passes with checkboxes and their settings, if the GUI is visible.
+// Passes can also be moved up and down the list.
+pub(crate) fn draw_frame_gui(passes: &mut RenderPassList, frame_state: &FrameState) {
+    let ui = match frame_state.gui {
+        Some(ui) => ui,
+        None => return,
+    };
+
+    if !ui
+        .collapsing_header(im_str!("Render passes"))
+        .default_open(false)
+        .build()
+    {
+        return;
+    }
+
+    let names = display_names(passes);
+    let mut moved: Option<(usize, usize)> = None;
+
+    for (idx, (pass, name)) in passes.iter_mut().zip(names.iter()).enumerate() {
+        {
+            let mut enabled_map = RENDER_PASS_ENABLED.lock().unwrap();
+            let enabled = enabled_map.entry(name.clone()).or_insert(true);
+            ui.checkbox(&im_str!("##enabled{}", idx), enabled);
+        }
+
+        ui.same_line(0.0);
+        if ui.button(&im_str!("Up##{}", idx), [0.0, 0.0]) && idx > 0 {
+            moved = Some((idx, idx - 1));
+        }
+        ui.same_line(0.0);
+        if ui.button(&im_str!("Down##{}", idx), [0.0, 0.0]) && idx + 1 < names.len() {
+            moved = Some((idx, idx + 1));
+        }
+
+        ui.same_line(0.0);
+        if ui
+            .collapsing_header(&im_str!("{}##settings{}", name, idx))
+            .default_open(false)
+            .build()
+        {
+            pass.draw_gui(ui);
+        }
+    }
+
+    if let Some((a, b)) = moved {
+        // Passes sharing a name swap suffixes, so their flags need to follow them
+        if passes[a].name() == passes[b].name() {
+            let mut enabled_map = RENDER_PASS_ENABLED.lock().unwrap();
+            let enabled_a = enabled_map.get(&names[a]).cloned().unwrap_or(true);
+            let enabled_b = enabled_map.get(&names[b]).cloned().unwrap_or(true);
+            enabled_map.insert(names[a].clone(), enabled_b);
+            enabled_map.insert(names[b].clone(), enabled_a);
+        }
+
+        passes.swap(a, b);
+    }
+}
+
+// Runs a `RenderPassList` with the enabled flags and order set in the GUI
+pub trait RenderPassListExt {
+    fn prepare_frame(
+        &mut self,
+        view_constants: &ViewConstants,
+        frame_state: &FrameState,
+        frame_idx: u32,
+    );
+}
+
+impl RenderPassListExt for RenderPassList {
+    fn prepare_frame(
+        &mut self,
+        view_constants: &ViewConstants,
+        frame_state: &FrameState,
+        frame_idx: u32,
+    ) {
+        let enabled = enabled_flags(self);
+        for (pass, enabled) in self.iter_mut().zip(enabled.into_iter()) {
+            if enabled {
+                pass.prepare_frame(view_constants, frame_state, frame_idx);
+            }
+        }
+
+        draw_frame_gui(self, frame_state);
+    }
+}
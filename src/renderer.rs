@@ -1,5 +1,6 @@
 use crate::gpu_debugger;
 use crate::gpu_profiler::{self, GpuProfilerStats};
+use crate::raster_stats;
 use crate::shader;
 use crate::vulkan::*;
 use ash::version::DeviceV1_0;
@@ -164,6 +165,7 @@ impl Renderer {
 
         gpu_profiler::end_frame();
         gpu_debugger::end_frame();
+        raster_stats::end_frame();
 
         self.gpu_profiler_stats = Some(gpu_profiler::get_stats());
         RenderFrameStatus::Ok
@@ -1,15 +1,21 @@
+use crate::camera_bookmarks;
+use crate::camera_path;
 use crate::gpu_debugger;
 use crate::gpu_profiler::GpuProfilerStats;
 use crate::gui::ImGuiBackend;
+use crate::history;
 use crate::keyboard::*;
+use crate::raster_stats;
+use crate::render_graph;
 use crate::renderer::{RenderFrameStatus, Renderer};
 use crate::texture::{Texture, TextureKey};
 use crate::vulkan;
-use crate::{Point2, Vector2};
+use crate::{CameraPathKey, Point2, Vector2};
 use ash::vk;
 use clap::ArgMatches;
 use imgui::im_str;
 use snoozy::{get_snapshot, OpaqueSnoozyRef, Result, SnoozyRef};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
@@ -46,6 +52,8 @@ pub struct MouseState {
     pub pos: Point2,
     pub delta: Vector2,
     pub button_mask: u32,
+    // In lines scrolled since the last frame; positive away from the user
+    pub wheel_delta: f32,
 }
 
 impl Default for MouseState {
@@ -54,6 +62,7 @@ impl Default for MouseState {
             pos: Point2::origin(),
             delta: Vector2::zeros(),
             button_mask: 0,
+            wheel_delta: 0.0,
         }
     }
 }
@@ -63,6 +72,7 @@ impl MouseState {
         self.delta = new_state.pos - self.pos;
         self.pos = new_state.pos;
         self.button_mask = new_state.button_mask;
+        self.wheel_delta = new_state.wheel_delta;
     }
 }
 
@@ -71,15 +81,21 @@ pub struct FrameState<'a> {
     pub keys: &'a KeyboardState,
     pub window_size_pixels: (u32, u32),
     pub dt: f32,
+    // Set while a camera path is playing; cameras should snap to it
+    pub camera_path_key: Option<CameraPathKey>,
+    // Set while the GUI is visible, for passes to draw their settings
+    pub gui: Option<&'a imgui::Ui<'a>>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct RendertoyConfig {
     pub width: u32,
     pub height: u32,
     pub vsync: bool,
     pub graphics_debugging: bool,
     pub device_index: usize,
+    // Camera path to play on startup; exits once it ends
+    pub camera_path: Option<PathBuf>,
 }
 
 fn parse_resolution(s: &str) -> Result<(u32, u32)> {
@@ -118,12 +134,15 @@ impl RendertoyConfig {
             .map(|val| FromStr::from_str(val).expect("Failed to parse device index"))
             .unwrap_or(0);
 
+        let camera_path = matches.value_of("camera-path").map(PathBuf::from);
+
         RendertoyConfig {
             width,
             height,
             vsync,
             graphics_debugging,
             device_index,
+            camera_path,
         }
     }
 }
@@ -145,6 +164,10 @@ impl Rendertoy {
             .expect("window");
         let window = Arc::new(window);
 
+        if let Some(path) = cfg.camera_path.as_ref() {
+            camera_path::start_playback(path).expect("Failed to load the camera path");
+        }
+
         let renderer = Renderer::new(
             window.clone(),
             cfg.graphics_debugging,
@@ -218,6 +241,12 @@ impl Rendertoy {
                     .long("ndebug")
                     .help("Disable graphics debugging"),
             )
+            .arg(
+                clap::Arg::with_name("camera-path")
+                    .long("camera-path")
+                    .help("Play a recorded camera path, and exit when it ends")
+                    .takes_value(true),
+            )
             .get_matches();
 
         Self::new_with_config(RendertoyConfig::from_args(&matches))
@@ -248,8 +277,10 @@ impl Rendertoy {
 
         let mut keyboard_events: Vec<KeyboardInput> = Vec::new();
         let mut new_mouse_state = self.state.mouse_state.clone();
+        new_mouse_state.wheel_delta = 0.0;
 
         let gui_want_capture_mouse = self.imgui.io().want_capture_mouse;
+        let gui_want_capture_keyboard = self.imgui.io().want_capture_keyboard;
 
         for event in events.iter() {
             #[allow(clippy::single_match)]
@@ -261,7 +292,10 @@ impl Rendertoy {
                             if input.state == ElementState::Pressed {
                                 self.state.show_gui = !self.state.show_gui;
                             }
-                        } else {
+                        } else if input.state == ElementState::Released
+                            || !gui_want_capture_keyboard
+                        {
+                            // Releases always go through, so keys don't get stuck
                             keyboard_events.push(*input);
                         }
                     }
@@ -290,6 +324,13 @@ impl Rendertoy {
                             new_mouse_state.button_mask &= !(1 << button_id);
                         }
                     }
+                    winit::WindowEvent::MouseWheel { delta, .. } if !gui_want_capture_mouse => {
+                        new_mouse_state.wheel_delta += match delta {
+                            winit::MouseScrollDelta::LineDelta(_, y) => *y,
+                            // Roughly one line per 20 logical pixels
+                            winit::MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.0,
+                        };
+                    }
                     _ => (),
                 },
                 _ => (),
@@ -325,15 +366,21 @@ impl Rendertoy {
             let render_result = self.renderer.render_frame(|renderer| {
                 let vk_state = self::vulkan::vk_state();
 
+                // Started before the frame callback, so that passes can add their settings
+                let ui = if state.show_gui {
+                    Some(imgui_backend.prepare_frame(&window, imgui, state.dt))
+                } else {
+                    None
+                };
+
                 let final_texture =
-                    state.draw_with_frame_snapshot(window_size_pixels, &mut callback);
+                    state.draw_with_frame_snapshot(window_size_pixels, ui.as_ref(), &mut callback);
                 let cb = vk_state.current_frame().command_buffer.lock().unwrap();
                 let cb = cb.cb;
 
                 let currently_debugged_texture = state.get_currently_debugged_texture().clone();
 
-                let gui_texture_view = if state.show_gui {
-                    let ui = imgui_backend.prepare_frame(&window, imgui, state.dt);
+                let gui_texture_view = if let Some(ui) = ui {
                     {
                         state.dump_next_frame_dot_graph =
                             ui.button(im_str!("Dump frame.dot"), [0.0, 0.0]);
@@ -355,6 +402,40 @@ impl Rendertoy {
                             }
                         }
 
+                        if ui
+                            .collapsing_header(im_str!("Raster instances"))
+                            .default_open(false)
+                            .build()
+                        {
+                            let stats = raster_stats::get_stats();
+                            ui.text(format!("Drawn: {}", stats.drawn_instances));
+                            ui.text(format!("Culled: {}", stats.culled_instances));
+                        }
+
+                        if ui
+                            .collapsing_header(im_str!("Render graph"))
+                            .default_open(false)
+                            .build()
+                        {
+                            render_graph::draw_gui(&ui);
+                        }
+
+                        if ui
+                            .collapsing_header(im_str!("Camera path"))
+                            .default_open(false)
+                            .build()
+                        {
+                            camera_path::draw_gui(&ui);
+                        }
+
+                        if ui
+                            .collapsing_header(im_str!("Camera bookmarks"))
+                            .default_open(false)
+                            .build()
+                        {
+                            camera_bookmarks::draw_gui(&ui);
+                        }
+
                         crate::warnings::with_drain_warnings(|warnings| {
                             if !warnings.is_empty() {
                                 if ui
@@ -391,6 +472,14 @@ impl Rendertoy {
             }
 
             running = self.next_frame();
+
+            if self.state.cfg.camera_path.is_some() && camera_path::is_playback_finished() {
+                tracing::info!(
+                    "Camera path finished; average CPU frame time: {:.2}ms",
+                    self.state.average_frame_time * 1000.0
+                );
+                running = false;
+            }
         }
     }
 }
@@ -405,6 +494,7 @@ impl RendertoyState {
     fn draw_with_frame_snapshot<F>(
         &mut self,
         window_size_pixels: (u32, u32),
+        gui: Option<&imgui::Ui>,
         callback: &mut F,
     ) -> vk::ImageView
     where
@@ -432,11 +522,20 @@ impl RendertoyState {
             );
         }
 
+        let camera_path_key = camera_path::begin_frame(self.dt);
+        history::begin_frame();
+
         let state = FrameState {
             mouse: &self.mouse_state,
             keys: &self.keyboard,
             window_size_pixels,
-            dt: self.dt,
+            dt: if camera_path_key.is_some() {
+                camera_path::CAMERA_PATH_PLAYBACK_DT
+            } else {
+                self.dt
+            },
+            camera_path_key,
+            gui,
         };
 
         let tex = callback(&state);
@@ -472,6 +571,12 @@ impl RendertoyState {
 
             let mut file = File::create("frame.dot").expect("File::create");
             file.write_all(dot.as_bytes()).expect("file.write_all");
+
+            if let Some(graph) = render_graph::last_compiled_graph() {
+                let mut file = File::create("render_passes.dot").expect("File::create");
+                file.write_all(graph.to_dot().as_bytes())
+                    .expect("file.write_all");
+            }
         }
 
         let debugged_texture = {
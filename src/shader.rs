@@ -1,6 +1,7 @@
 use crate::blob::*;
-use crate::buffer::Buffer;
+use crate::buffer::{Buffer, BufferKey};
 use crate::gpu_debugger;
+use crate::raster_stats;
 use crate::texture::{Texture, TextureKey};
 use crate::vulkan::*;
 use ash::version::DeviceV1_0;
@@ -167,10 +168,22 @@ impl ShaderUniformHolder {
     }
 }
 
+impl ResolvedShaderUniformHolder {
+    pub(crate) fn new(name: &str, value: ResolvedShaderUniformValue) -> Self {
+        Self {
+            name: name.to_owned(),
+            payload: ResolvedShaderUniformPayload {
+                value,
+                warn_if_unreferenced: true,
+            },
+        }
+    }
+}
+
 pub type ShaderUniformBundle = Vec<ShaderUniformHolder>;
 pub type ResolvedShaderUniformBundle = Vec<ResolvedShaderUniformHolder>;
 
-async fn resolve(
+pub(crate) async fn resolve(
     ctx: Context,
     uniforms: Vec<ShaderUniformHolder>,
 ) -> Result<Vec<ResolvedShaderUniformHolder>> {
@@ -762,10 +775,34 @@ pub struct RasterPipeline {
 unsafe impl Send for RasterPipeline {}
 unsafe impl Sync for RasterPipeline {}
 
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RasterTopology {
+    Triangles,
+    // Draws every index as a point. Vertex shaders must write `gl_PointSize`.
+    Points,
+}
+
 #[snoozy]
 pub async fn make_raster_pipeline_snoozy(
+    ctx: Context,
+    shaders_in: &Vec<SnoozyRef<RasterSubShader>>,
+) -> Result<RasterPipeline> {
+    make_raster_pipeline_impl(ctx, shaders_in, RasterTopology::Triangles).await
+}
+
+#[snoozy]
+pub async fn make_raster_pipeline_with_topology_snoozy(
+    ctx: Context,
+    shaders_in: &Vec<SnoozyRef<RasterSubShader>>,
+    topology: &RasterTopology,
+) -> Result<RasterPipeline> {
+    make_raster_pipeline_impl(ctx, shaders_in, *topology).await
+}
+
+async fn make_raster_pipeline_impl(
     mut ctx: Context,
     shaders_in: &Vec<SnoozyRef<RasterSubShader>>,
+    topology: RasterTopology,
 ) -> Result<RasterPipeline> {
     use std::ffi::CString;
 
@@ -892,7 +929,10 @@ pub async fn make_raster_pipeline_snoozy(
             ..Default::default()
         };
         let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            topology: match topology {
+                RasterTopology::Triangles => vk::PrimitiveTopology::TRIANGLE_LIST,
+                RasterTopology::Points => vk::PrimitiveTopology::POINT_LIST,
+            },
             ..Default::default()
         };
 
@@ -1447,15 +1487,68 @@ pub async fn compute_tex_snoozy(
     let cs = ctx.get(cs).await?;
     ctx.set_debug_name(&cs.name);
 
-    let mut uniforms = resolve(ctx, uniforms.clone()).await?;
-    uniforms.push(ResolvedShaderUniformHolder {
-        name: "outputTex".to_owned(),
-        payload: ResolvedShaderUniformPayload {
-            value: ResolvedShaderUniformValue::RwTexture(output_tex.clone()),
-            warn_if_unreferenced: true,
+    let uniforms = resolve(ctx, uniforms.clone()).await?;
+    dispatch_compute_tex(&cs, &output_tex, uniforms)?;
+
+    Ok(output_tex)
+}
+
+// Records a dispatch of `cs` over `output_tex`, which is bound as `outputTex`.
+// The previous contents of `output_tex` are discarded.
+pub(crate) fn dispatch_compute_tex(
+    cs: &ComputeShader,
+    output_tex: &Texture,
+    mut uniforms: Vec<ResolvedShaderUniformHolder>,
+) -> Result<()> {
+    let key = &output_tex.key;
+    uniforms.push(ResolvedShaderUniformHolder::new(
+        "outputTex",
+        ResolvedShaderUniformValue::RwTexture(output_tex.clone()),
+    ));
+
+    dispatch_compute(
+        cs,
+        uniforms,
+        (key.width, key.height, 1),
+        |device, cb| {
+            record_image_barrier(
+                device,
+                cb,
+                ImageBarrier::new(
+                    output_tex.image,
+                    vk_sync::AccessType::Nothing,
+                    vk_sync::AccessType::ComputeShaderWrite,
+                )
+                .with_discard(true),
+            );
         },
-    });
+        |device, cb| {
+            record_image_barrier(
+                device,
+                cb,
+                ImageBarrier::new(
+                    output_tex.image,
+                    vk_sync::AccessType::ComputeShaderWrite,
+                    vk_sync::AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+                ),
+            );
+        },
+    )?;
 
+    gpu_debugger::report_texture(&cs.name, output_tex.view);
+
+    Ok(())
+}
+
+// Records a dispatch of `cs` over `thread_count` invocations, rounded up to whole groups.
+// `before` and `after` record the barriers for the outputs around the dispatch.
+fn dispatch_compute(
+    cs: &ComputeShader,
+    uniforms: Vec<ResolvedShaderUniformHolder>,
+    thread_count: (u32, u32, u32),
+    before: impl FnOnce(&Device, vk::CommandBuffer),
+    after: impl FnOnce(&Device, vk::CommandBuffer),
+) -> Result<()> {
     let (vk, vk_state) = vk_all();
     let vk_frame = vk_state.current_frame();
 
@@ -1505,18 +1598,9 @@ pub async fn compute_tex_snoozy(
     let cb = vk_frame.command_buffer.lock().unwrap();
     let cb: vk::CommandBuffer = cb.cb;
 
-    unsafe {
-        record_image_barrier(
-            &vk.device,
-            cb,
-            ImageBarrier::new(
-                output_tex.image,
-                vk_sync::AccessType::Nothing,
-                vk_sync::AccessType::ComputeShaderWrite,
-            )
-            .with_discard(true),
-        );
+    before(&vk.device, cb);
 
+    unsafe {
         let mut descriptor_sets = descriptor_sets;
 
         for idx in ds_update_result.all_buffers_descriptor_set_idx.iter() {
@@ -1552,9 +1636,9 @@ pub async fn compute_tex_snoozy(
 
         vk.device.cmd_dispatch(
             cb,
-            (key.width + cs.local_size.0 - 1) / cs.local_size.0,
-            (key.height + cs.local_size.1 - 1) / cs.local_size.1,
-            1,
+            (thread_count.0 + cs.local_size.0 - 1) / cs.local_size.0,
+            (thread_count.1 + cs.local_size.1 - 1) / cs.local_size.1,
+            (thread_count.2 + cs.local_size.2 - 1) / cs.local_size.2,
         );
 
         vk.device.cmd_write_timestamp(
@@ -1563,22 +1647,70 @@ pub async fn compute_tex_snoozy(
             vk_frame.profiler_data.query_pool,
             vk_query_idx * 2 + 1,
         );
-
-        record_image_barrier(
-            &vk.device,
-            cb,
-            ImageBarrier::new(
-                output_tex.image,
-                vk_sync::AccessType::ComputeShaderWrite,
-                vk_sync::AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
-            ),
-        );
     }
 
+    after(&vk.device, cb);
+
     uniform_source.report_unreferenced_uniform_warnings(&cs.name);
-    gpu_debugger::report_texture(&cs.name, output_tex.view);
 
-    Ok(output_tex)
+    Ok(())
+}
+
+// Dispatches `cs` over `thread_count` invocations, with the result written to a new buffer
+// bound as the `outputBuffer` storage block.
+#[snoozy]
+pub async fn compute_buffer_snoozy(
+    mut ctx: Context,
+    key: &BufferKey,
+    thread_count: &(u32, u32, u32),
+    cs: &SnoozyRef<ComputeShader>,
+    uniforms: &Vec<ShaderUniformHolder>,
+) -> Result<Buffer> {
+    let output_buf = crate::backend::buffer::create_buffer(*key);
+    let cs = ctx.get(cs).await?;
+    ctx.set_debug_name(&cs.name);
+
+    let mut uniforms = resolve(ctx, uniforms.clone()).await?;
+    uniforms.push(ResolvedShaderUniformHolder::new(
+        "outputBuffer",
+        ResolvedShaderUniformValue::Buffer(output_buf.clone()),
+    ));
+
+    dispatch_compute(
+        &cs,
+        uniforms,
+        *thread_count,
+        |device, cb| {
+            // Pooled buffers may still be read by previous users
+            vk_sync::cmd::pipeline_barrier(
+                device.fp_v1_0(),
+                cb,
+                Some(vk_sync::GlobalBarrier {
+                    previous_accesses: &[vk_sync::AccessType::AnyShaderReadOther],
+                    next_accesses: &[vk_sync::AccessType::ComputeShaderWrite],
+                }),
+                &[],
+                &[],
+            );
+        },
+        |device, cb| {
+            vk_sync::cmd::pipeline_barrier(
+                device.fp_v1_0(),
+                cb,
+                Some(vk_sync::GlobalBarrier {
+                    previous_accesses: &[vk_sync::AccessType::ComputeShaderWrite],
+                    next_accesses: &[
+                        vk_sync::AccessType::AnyShaderReadOther,
+                        vk_sync::AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+                    ],
+                }),
+                &[],
+                &[],
+            );
+        },
+    )?;
+
+    Ok(output_buf)
 }
 
 #[snoozy]
@@ -1775,6 +1907,8 @@ pub async fn raster_tex_snoozy(
     }
 
     let mut mesh_stack = vec![MeshDrawData::default()];
+    let mut drawn_instance_count = 0u32;
+    let mut culled_instance_count = 0u32;
 
     let flattened_uniforms: HashMap<String, ResolvedShaderUniformPayload> = HashMap::new();
     let mut uniform_source = TrackedUniformParamSource {
@@ -1782,8 +1916,20 @@ pub async fn raster_tex_snoozy(
         requested: HashSet::new(),
     };
 
+    // Uploaded with every raster mesh, but only used by shaders which draw meshlets
+    const MESHLET_UNIFORM_NAMES: [&str; 4] = [
+        "mesh_meshlet_count",
+        "mesh_meshlet_buf",
+        "mesh_meshlet_vertex_buf",
+        "mesh_meshlet_triangle_buf",
+    ];
+
     flatten_uniforms(uniforms, &mut |e| match e {
         FlattenedUniformEvent::SetUniform { name, mut payload } => {
+            if MESHLET_UNIFORM_NAMES.contains(&name.as_str()) {
+                payload.warn_if_unreferenced = false;
+            }
+
             match payload.value {
                 ResolvedShaderUniformValue::Buffer(ref buf) if name == "mesh_index_buf" => {
                     mesh_stack.last_mut().unwrap().index_buffer = Some(buf.buffer);
@@ -1793,6 +1939,12 @@ pub async fn raster_tex_snoozy(
                     mesh_stack.last_mut().unwrap().index_count = Some(value);
                     payload.warn_if_unreferenced = false;
                 }
+                ResolvedShaderUniformValue::Uint32(value)
+                    if name == "raster_culled_instance_count" =>
+                {
+                    culled_instance_count += value;
+                    payload.warn_if_unreferenced = false;
+                }
                 _ => {}
             }
 
@@ -1810,6 +1962,7 @@ pub async fn raster_tex_snoozy(
                         vk.device
                             .cmd_bind_index_buffer(cb, index_buffer, 0, vk::IndexType::UINT32);
                         vk.device.cmd_draw_indexed(cb, index_count as _, 1, 0, 0, 0);
+                        drawn_instance_count += 1;
                         //println!("-------");
                     }
                 }
@@ -1832,6 +1985,7 @@ pub async fn raster_tex_snoozy(
     };
 
     uniform_source.report_unreferenced_uniform_warnings("mesh_raster");
+    raster_stats::report_raster_pass(drawn_instance_count, culled_instance_count);
     gpu_debugger::report_texture("mesh_raster", output_tex.view);
 
     Ok(output_tex)
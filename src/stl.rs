@@ -0,0 +1,150 @@
+use super::*;
+
+struct StlTriangle {
+    normal: [f32; 3],
+    verts: [[f32; 3]; 3],
+}
+
+fn read_f32_le(data: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn parse_binary_stl(data: &[u8]) -> Option<Vec<StlTriangle>> {
+    if data.len() < 84 {
+        return None;
+    }
+
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+
+    // ASCII files can start with an 80-byte `solid ...` line too, so only trust the size
+    if data.len() != 84 + count * 50 {
+        return None;
+    }
+
+    Some(
+        (0..count)
+            .map(|i| {
+                let offset = 84 + i * 50;
+                let read_vec3 = |o: usize| {
+                    [
+                        read_f32_le(data, offset + o),
+                        read_f32_le(data, offset + o + 4),
+                        read_f32_le(data, offset + o + 8),
+                    ]
+                };
+
+                StlTriangle {
+                    normal: read_vec3(0),
+                    verts: [read_vec3(12), read_vec3(24), read_vec3(36)],
+                }
+            })
+            .collect(),
+    )
+}
+
+fn parse_ascii_stl(data: &[u8]) -> Result<Vec<StlTriangle>> {
+    let source = std::str::from_utf8(data)?;
+    let mut res = Vec::new();
+
+    let mut normal = [0.0f32; 3];
+    let mut verts: Vec<[f32; 3]> = Vec::with_capacity(3);
+
+    let parse_vec3 = |tokens: &mut std::str::SplitWhitespace| -> Result<[f32; 3]> {
+        let mut v = [0.0f32; 3];
+        for c in v.iter_mut() {
+            *c = tokens
+                .next()
+                .ok_or_else(|| format_err!("expected 3 components"))?
+                .parse()?;
+        }
+        Ok(v)
+    };
+
+    for line in source.lines() {
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("facet") => {
+                // facet normal nx ny nz
+                tokens.next();
+                normal = parse_vec3(&mut tokens)?;
+                verts.clear();
+            }
+            Some("vertex") => verts.push(parse_vec3(&mut tokens)?),
+            Some("endfacet") => {
+                if verts.len() >= 3 {
+                    // Fan triangulation, in case of non-standard polygonal facets
+                    for i in 1..verts.len() - 1 {
+                        res.push(StlTriangle {
+                            normal,
+                            verts: [verts[0], verts[i], verts[i + 1]],
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(res)
+}
+
+// Loads a binary or ASCII STL file. STL has no shared vertices, so the result is flat-shaded,
+// with normals calculated from the winding order when the file doesn't provide them.
+#[snoozy(cache)]
+pub async fn load_stl_mesh_snoozy(
+    mut ctx: Context,
+    path: &AssetPath,
+    scale: &f32,
+) -> Result<TriangleMesh> {
+    let blob = ctx.get(&load_blob(path.clone())).await?;
+
+    let triangles = if let Some(triangles) = parse_binary_stl(&blob.contents) {
+        triangles
+    } else {
+        parse_ascii_stl(&blob.contents).map_err(|err| format_err!("{}: {}", path, err))?
+    };
+
+    let mut res = TriangleMesh::default();
+    let material_id = res.add_default_material();
+
+    for tri in triangles.iter() {
+        let p: Vec<Point3> = tri
+            .verts
+            .iter()
+            .map(|v| Point3::from(*v) * *scale)
+            .collect();
+
+        let normal = Vector3::from(tri.normal);
+        let normal = if normal.norm_squared() > 1e-12 {
+            normal.normalize()
+        } else {
+            (p[1] - p[0])
+                .cross(&(p[2] - p[0]))
+                .try_normalize(1e-20)
+                .unwrap_or_else(Vector3::y)
+        };
+
+        let base_index = res.positions.len() as u32;
+        res.indices
+            .extend_from_slice(&[base_index, base_index + 1, base_index + 2]);
+
+        for v in p.iter() {
+            res.positions.push([v.x, v.y, v.z]);
+            res.normals.push([normal.x, normal.y, normal.z]);
+            res.colors.push([1.0, 1.0, 1.0, 1.0]);
+            res.uvs.push([0.0, 0.0]);
+            res.material_ids.push(material_id);
+        }
+    }
+
+    // STL has no UVs, so there's nothing for MikkTSpace to go by
+    res.generate_arbitrary_tangents();
+
+    Ok(res)
+}
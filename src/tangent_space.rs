@@ -0,0 +1,242 @@
+use super::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NormalGeneration {
+    // Averaged over the triangles sharing a position, weighted by the angle of their corners
+    Smooth,
+    // Face normals; vertices shared between triangles get duplicated
+    Flat,
+}
+
+fn corner_angle(p: &Point3, a: &Point3, b: &Point3) -> f32 {
+    let (e0, e1) = (a - p, b - p);
+    let denom = (e0.norm_squared() * e1.norm_squared()).sqrt();
+
+    if denom > 0.0 {
+        (e0.dot(&e1) / denom).max(-1.0).min(1.0).acos()
+    } else {
+        0.0
+    }
+}
+
+// Applies the vertex remapping returned by `TriangleMesh::generate_normals` to per-vertex data
+pub fn remap_vertex_data<T: Copy>(data: &[T], remap: &[u32]) -> Vec<T> {
+    remap.iter().map(|i| data[*i as usize]).collect()
+}
+
+impl TriangleMesh {
+    fn face_normal(&self, tri: &[u32]) -> Vector3 {
+        let p0 = Point3::from(self.positions[tri[0] as usize]);
+        let p1 = Point3::from(self.positions[tri[1] as usize]);
+        let p2 = Point3::from(self.positions[tri[2] as usize]);
+        (p1 - p0).cross(&(p2 - p0))
+    }
+
+    fn push_vertex_copy(&mut self, src: usize) -> u32 {
+        let idx = self.positions.len() as u32;
+
+        self.positions.push(self.positions[src]);
+        self.normals.push(self.normals[src]);
+        if !self.colors.is_empty() {
+            self.colors.push(self.colors[src]);
+        }
+        if !self.uvs.is_empty() {
+            self.uvs.push(self.uvs[src]);
+        }
+        if !self.tangents.is_empty() {
+            self.tangents.push(self.tangents[src]);
+        }
+        if !self.material_ids.is_empty() {
+            self.material_ids.push(self.material_ids[src]);
+        }
+
+        idx
+    }
+
+    // Calculates normals for vertices for which `has_normal` is false; other normals are kept.
+    // Flat shading needs vertices which aren't shared between triangles, so it can add vertices.
+    // Returns the index of the source vertex of every vertex in the resulting mesh, so that
+    // data stored alongside the mesh (e.g. skinning weights) can be remapped accordingly.
+    pub fn generate_normals(&mut self, mode: NormalGeneration, has_normal: &[bool]) -> Vec<u32> {
+        let vertex_count = self.positions.len();
+        let mut remap: Vec<u32> = (0..vertex_count as u32).collect();
+
+        self.normals.resize(vertex_count, [0.0, 0.0, 0.0]);
+
+        match mode {
+            NormalGeneration::Smooth => {
+                // Vertices are commonly split along UV seams and material boundaries, so accumulate
+                // per position instead of per vertex to avoid shading seams.
+                let mut position_ids: HashMap<[u32; 3], usize> = HashMap::new();
+                let vertex_position_ids: Vec<usize> = self
+                    .positions
+                    .iter()
+                    .map(|p| {
+                        let key = [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+                        let next_id = position_ids.len();
+                        *position_ids.entry(key).or_insert(next_id)
+                    })
+                    .collect();
+
+                let mut accum = vec![Vector3::zeros(); position_ids.len()];
+
+                for tri in self.indices.chunks_exact(3) {
+                    if tri.iter().all(|i| has_normal[*i as usize]) {
+                        continue;
+                    }
+
+                    let face_normal = if let Some(n) = self.face_normal(tri).try_normalize(0.0) {
+                        n
+                    } else {
+                        continue;
+                    };
+
+                    let p: Vec<Point3> = tri
+                        .iter()
+                        .map(|i| Point3::from(self.positions[*i as usize]))
+                        .collect();
+
+                    for corner in 0..3 {
+                        let angle =
+                            corner_angle(&p[corner], &p[(corner + 1) % 3], &p[(corner + 2) % 3]);
+                        accum[vertex_position_ids[tri[corner] as usize]] += face_normal * angle;
+                    }
+                }
+
+                for (i, n) in self.normals.iter_mut().enumerate() {
+                    if !has_normal[i] {
+                        let v = accum[vertex_position_ids[i]]
+                            .try_normalize(0.0)
+                            .unwrap_or_else(Vector3::y);
+                        *n = [v.x, v.y, v.z];
+                    }
+                }
+            }
+            NormalGeneration::Flat => {
+                // The first triangle to use a vertex gets to keep it; others use copies
+                let mut vertex_claimed = vec![false; vertex_count];
+
+                for tri_idx in 0..self.indices.len() / 3 {
+                    let tri = [
+                        self.indices[tri_idx * 3],
+                        self.indices[tri_idx * 3 + 1],
+                        self.indices[tri_idx * 3 + 2],
+                    ];
+
+                    let n = self
+                        .face_normal(&tri)
+                        .try_normalize(0.0)
+                        .unwrap_or_else(Vector3::y);
+
+                    for (corner, vertex_idx) in tri.iter().enumerate() {
+                        let vertex_idx = *vertex_idx as usize;
+                        if has_normal[vertex_idx] {
+                            continue;
+                        }
+
+                        let dst = if vertex_claimed[vertex_idx] {
+                            let copy = self.push_vertex_copy(vertex_idx);
+                            remap.push(vertex_idx as u32);
+                            self.indices[tri_idx * 3 + corner] = copy;
+                            copy as usize
+                        } else {
+                            vertex_claimed[vertex_idx] = true;
+                            vertex_idx
+                        };
+
+                        self.normals[dst] = [n.x, n.y, n.z];
+                    }
+                }
+
+                for (i, n) in self.normals.iter_mut().enumerate().take(vertex_count) {
+                    if !has_normal[i] && !vertex_claimed[i] {
+                        *n = [0.0, 1.0, 0.0];
+                    }
+                }
+            }
+        }
+
+        remap
+    }
+
+    // Calculates MikkTSpace tangents for vertices for which `has_tangent` is false,
+    // matching what normal maps are generally baked with. Requires normals and UVs.
+    pub fn generate_tangents(&mut self, has_tangent: &[bool]) {
+        let vertex_count = self.positions.len();
+        self.tangents.resize(vertex_count, [1.0, 0.0, 0.0, 1.0]);
+        self.uvs.resize(vertex_count, [0.0, 0.0]);
+
+        let mut geometry = MikktspaceGeometry {
+            mesh: self,
+            has_tangent,
+        };
+
+        if !mikktspace::generate_tangents(&mut geometry) {
+            tracing::warn!("MikkTSpace tangent generation failed");
+        }
+    }
+
+    // Picks an arbitrary tangent perpendicular to the normal of every vertex, for meshes
+    // without UVs to derive tangents from. Normal maps can't be used with those anyway,
+    // but shaders still get a valid tangent frame.
+    pub fn generate_arbitrary_tangents(&mut self) {
+        self.tangents = self
+            .normals
+            .iter()
+            .map(|n| {
+                let n = Vector3::from(*n);
+                let axis = if n.x.abs() < 0.9 {
+                    Vector3::x()
+                } else {
+                    Vector3::y()
+                };
+                let t = (axis - n * n.dot(&axis))
+                    .try_normalize(1e-20)
+                    .unwrap_or(axis);
+                [t.x, t.y, t.z, 1.0]
+            })
+            .collect();
+    }
+}
+
+struct MikktspaceGeometry<'a> {
+    mesh: &'a mut TriangleMesh,
+    has_tangent: &'a [bool],
+}
+
+impl<'a> MikktspaceGeometry<'a> {
+    fn vertex_index(&self, face: usize, vert: usize) -> usize {
+        self.mesh.indices[face * 3 + vert] as usize
+    }
+}
+
+impl<'a> mikktspace::Geometry for MikktspaceGeometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.mesh.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.mesh.positions[self.vertex_index(face, vert)]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.mesh.normals[self.vertex_index(face, vert)]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.mesh.uvs[self.vertex_index(face, vert)]
+    }
+
+    // The sign in `w` follows the glTF convention: bitangent = cross(normal, tangent.xyz) * w
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let idx = self.vertex_index(face, vert);
+        if !self.has_tangent[idx] {
+            self.mesh.tangents[idx] = tangent;
+        }
+    }
+}
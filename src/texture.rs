@@ -5,6 +5,7 @@ use crate::blob::{load_blob, AssetPath, Blob};
 pub use ash::{vk, vk::Format};
 
 use snoozy::*;
+use std::hash::{Hash, Hasher};
 
 #[derive(Serialize, Debug, PartialEq, Eq, Abomonation, Clone, Copy)]
 pub enum TexGamma {
@@ -12,9 +13,34 @@ pub enum TexGamma {
     Srgb,
 }
 
+#[derive(Serialize, Debug, PartialEq, Eq, Abomonation, Clone, Copy)]
+pub enum TexChannels {
+    R,
+    Rg,
+    Rgba,
+}
+
+impl TexChannels {
+    fn count(self) -> usize {
+        match self {
+            TexChannels::R => 1,
+            TexChannels::Rg => 2,
+            TexChannels::Rgba => 4,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Abomonation, Clone, Copy)]
+pub enum TexBitDepth {
+    U8,
+    U16,
+}
+
 #[derive(Serialize, Debug, Clone, Copy, Abomonation)]
 pub struct TexParams {
     pub gamma: TexGamma,
+    pub channels: TexChannels,
+    pub bit_depth: TexBitDepth,
 }
 
 #[snoozy]
@@ -24,6 +50,8 @@ pub async fn load_tex_snoozy(mut ctx: Context, path: &AssetPath) -> Result<Textu
             path.clone(),
             TexParams {
                 gamma: TexGamma::Srgb,
+                channels: TexChannels::Rgba,
+                bit_depth: TexBitDepth::U8,
             },
         ))
         .await?;
@@ -31,36 +59,248 @@ pub async fn load_tex_snoozy(mut ctx: Context, path: &AssetPath) -> Result<Textu
 }
 
 #[derive(Abomonation, Clone)]
-pub struct RawRgba8Image {
+pub struct RawLdrImage {
     data: Vec<u8>,
     dimensions: (u32, u32),
+    channels: TexChannels,
+    bit_depth: TexBitDepth,
 }
 
-#[snoozy(cache)]
-pub async fn load_raw_ldr_tex_snoozy(mut ctx: Context, path: &AssetPath) -> Result<RawRgba8Image> {
-    use image::GenericImageView;
+// Samples as stored in the source file, before any channel or bit depth conversion.
+enum DecodedLdrSamples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+}
 
-    let blob = ctx.get(&load_blob(path.clone())).await?;
+struct DecodedLdrImage {
+    samples: DecodedLdrSamples,
+    channel_count: usize,
+    dimensions: (u32, u32),
+}
+
+fn decode_png16(contents: &[u8]) -> Result<Option<DecodedLdrImage>> {
+    use image::ImageDecoder;
+
+    let decoder = image::png::PNGDecoder::new(std::io::Cursor::new(contents))?;
+    let channel_count = match decoder.colortype() {
+        image::ColorType::Gray(16) => 1,
+        image::ColorType::GrayA(16) => 2,
+        image::ColorType::RGB(16) => 3,
+        image::ColorType::RGBA(16) => 4,
+        _ => return Ok(None),
+    };
+
+    let (width, height) = decoder.dimensions();
+    let data = decoder.read_image()?;
+
+    // PNG stores 16-bit samples big-endian
+    let samples = data
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
 
-    let image = image::load_from_memory(&*blob.contents)?;
-    let image_dimensions = image.dimensions();
-    tracing::info!("Loaded image: {:?} {:?}", image_dimensions, image.color());
+    Ok(Some(DecodedLdrImage {
+        samples: DecodedLdrSamples::U16(samples),
+        channel_count,
+        dimensions: (width as u32, height as u32),
+    }))
+}
+
+fn decode_ldr_image(contents: &[u8]) -> Result<DecodedLdrImage> {
+    use image::{DynamicImage, GenericImageView};
+
+    // `image` only decodes 8-bit data into a `DynamicImage`, so 16-bit PNGs need to go
+    // through the decoder directly.
+    if let Ok(image::ImageFormat::PNG) = image::guess_format(contents) {
+        if let Some(image) = decode_png16(contents)? {
+            return Ok(image);
+        }
+    }
+
+    let image = image::load_from_memory(contents)?;
+    let dimensions = image.dimensions();
 
-    let image = image.to_rgba();
+    let (samples, channel_count) = match image {
+        DynamicImage::ImageLuma8(img) => (img.into_raw(), 1),
+        DynamicImage::ImageLumaA8(img) => (img.into_raw(), 2),
+        DynamicImage::ImageRgb8(img) => (img.into_raw(), 3),
+        DynamicImage::ImageRgba8(img) => (img.into_raw(), 4),
+        DynamicImage::ImageBgr8(_) => (image.to_rgb().into_raw(), 3),
+        DynamicImage::ImageBgra8(_) => (image.to_rgba().into_raw(), 4),
+    };
 
-    Ok(RawRgba8Image {
-        data: image.into_raw(),
-        dimensions: image_dimensions,
+    Ok(DecodedLdrImage {
+        samples: DecodedLdrSamples::U8(samples),
+        channel_count,
+        dimensions,
     })
 }
 
-fn load_ldr_tex(image: &RawRgba8Image, params: &TexParams) -> Result<Texture> {
-    let internal_format = if params.gamma == TexGamma::Linear {
-        vk::Format::R8G8B8A8_UNORM
-    } else {
-        vk::Format::R8G8B8A8_SRGB
+// Greyscale sources are splatted into color channels, and alpha is kept in the last channel.
+// Color sources provide their leading channels verbatim, so that e.g. a two-channel normal map
+// stored in an RGB file can be loaded as `Rg`.
+fn remap_channels<T: Copy>(src: &[T], src_count: usize, dst_count: usize, one: T) -> Vec<T> {
+    let mut dst = Vec::with_capacity(src.len() / src_count * dst_count);
+
+    for px in src.chunks_exact(src_count) {
+        let alpha = if src_count == 2 || src_count == 4 {
+            px[src_count - 1]
+        } else {
+            one
+        };
+
+        match (src_count, dst_count) {
+            (1, _) | (2, _) => {
+                let luma = px[0];
+                match dst_count {
+                    1 => dst.push(luma),
+                    2 => dst.extend_from_slice(&[luma, alpha]),
+                    _ => dst.extend_from_slice(&[luma, luma, luma, alpha]),
+                }
+            }
+            (_, 1) => dst.push(px[0]),
+            (_, 2) => dst.extend_from_slice(&[px[0], px[1]]),
+            _ => dst.extend_from_slice(&[px[0], px[1], px[2], alpha]),
+        }
+    }
+
+    dst
+}
+
+fn convert_ldr_image(
+    image: DecodedLdrImage,
+    channels: TexChannels,
+    bit_depth: TexBitDepth,
+) -> RawLdrImage {
+    let src_count = image.channel_count;
+    let dst_count = channels.count();
+
+    let data = match (image.samples, bit_depth) {
+        (DecodedLdrSamples::U8(samples), TexBitDepth::U8) => {
+            remap_channels(&samples, src_count, dst_count, 255u8)
+        }
+        (DecodedLdrSamples::U8(samples), TexBitDepth::U16) => {
+            let samples: Vec<u16> = samples.into_iter().map(|v| v as u16 * 257).collect();
+            crate::buffer::to_byte_vec(remap_channels(&samples, src_count, dst_count, 0xffffu16))
+        }
+        (DecodedLdrSamples::U16(samples), TexBitDepth::U8) => {
+            let samples: Vec<u8> = samples
+                .into_iter()
+                .map(|v| ((v as u32 + 128) / 257) as u8)
+                .collect();
+            remap_channels(&samples, src_count, dst_count, 255u8)
+        }
+        (DecodedLdrSamples::U16(samples), TexBitDepth::U16) => {
+            crate::buffer::to_byte_vec(remap_channels(&samples, src_count, dst_count, 0xffffu16))
+        }
     };
 
+    RawLdrImage {
+        data,
+        dimensions: image.dimensions,
+        channels,
+        bit_depth,
+    }
+}
+
+fn decode_and_convert_ldr_image(
+    contents: &[u8],
+    channels: TexChannels,
+    bit_depth: TexBitDepth,
+) -> Result<RawLdrImage> {
+    let image = decode_ldr_image(contents)?;
+    tracing::info!(
+        "Loaded image: {:?} with {} channel(s) at {} bits",
+        image.dimensions,
+        image.channel_count,
+        if let DecodedLdrSamples::U16(_) = image.samples {
+            16
+        } else {
+            8
+        }
+    );
+
+    Ok(convert_ldr_image(image, channels, bit_depth))
+}
+
+#[snoozy(cache)]
+pub async fn load_raw_ldr_tex_snoozy(
+    mut ctx: Context,
+    path: &AssetPath,
+    channels: &TexChannels,
+    bit_depth: &TexBitDepth,
+) -> Result<RawLdrImage> {
+    let blob = ctx.get(&load_blob(path.clone())).await?;
+    decode_and_convert_ldr_image(&*blob.contents, *channels, *bit_depth)
+}
+
+// Encoded (PNG, JPEG, ...) image data which doesn't live in its own asset file,
+// such as textures embedded in glTF buffers. Hashed by its contents only,
+// so that identical images share one texture.
+#[derive(Abomonation, Clone)]
+pub struct InMemoryImage {
+    content_hash: u64,
+    encoded: Vec<u8>,
+}
+
+impl InMemoryImage {
+    pub fn new(encoded: Vec<u8>) -> Self {
+        let mut s = DefaultSnoozyHash::default();
+        encoded.hash(&mut s);
+        let content_hash = s.finish();
+
+        Self {
+            content_hash,
+            encoded,
+        }
+    }
+}
+
+impl Hash for InMemoryImage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.content_hash.hash(state);
+    }
+}
+
+// Only the hash, so that op lookups don't go through the encoded bytes
+impl serde::Serialize for InMemoryImage {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde::Serialize::serialize(&self.content_hash, serializer)
+    }
+}
+
+fn get_ldr_tex_format(
+    channels: TexChannels,
+    bit_depth: TexBitDepth,
+    gamma: TexGamma,
+) -> vk::Format {
+    match (bit_depth, channels, gamma) {
+        (TexBitDepth::U8, TexChannels::R, TexGamma::Linear) => vk::Format::R8_UNORM,
+        (TexBitDepth::U8, TexChannels::R, TexGamma::Srgb) => vk::Format::R8_SRGB,
+        (TexBitDepth::U8, TexChannels::Rg, TexGamma::Linear) => vk::Format::R8G8_UNORM,
+        (TexBitDepth::U8, TexChannels::Rg, TexGamma::Srgb) => vk::Format::R8G8_SRGB,
+        (TexBitDepth::U8, TexChannels::Rgba, TexGamma::Linear) => vk::Format::R8G8B8A8_UNORM,
+        (TexBitDepth::U8, TexChannels::Rgba, TexGamma::Srgb) => vk::Format::R8G8B8A8_SRGB,
+        (TexBitDepth::U16, TexChannels::R, _) => vk::Format::R16_UNORM,
+        (TexBitDepth::U16, TexChannels::Rg, _) => vk::Format::R16G16_UNORM,
+        (TexBitDepth::U16, TexChannels::Rgba, _) => vk::Format::R16G16B16A16_UNORM,
+    }
+}
+
+// There are no 16-bit sRGB formats; colour maps get decoded at 8 bits instead.
+fn get_ldr_bit_depth(params: &TexParams) -> TexBitDepth {
+    if params.gamma == TexGamma::Srgb {
+        TexBitDepth::U8
+    } else {
+        params.bit_depth
+    }
+}
+
+fn load_ldr_tex(image: &RawLdrImage, params: &TexParams) -> Result<Texture> {
+    let internal_format = get_ldr_tex_format(image.channels, image.bit_depth, params.gamma);
     load_tex_impl(&image.data, image.dimensions, internal_format)
 }
 
@@ -203,11 +443,43 @@ pub async fn load_tex_with_params_snoozy(
         let blob = ctx.get(&load_blob(path.clone())).await?;
         load_hdr_tex(&*blob, params)
     } else {
-        let raw_img = ctx.get(&load_raw_ldr_tex(path.clone())).await?;
+        let raw_img = ctx
+            .get(&load_raw_ldr_tex(
+                path.clone(),
+                params.channels,
+                get_ldr_bit_depth(params),
+            ))
+            .await?;
         load_ldr_tex(&*raw_img, params)
     }
 }
 
+#[snoozy(cache)]
+pub async fn load_raw_ldr_tex_from_memory_snoozy(
+    _ctx: Context,
+    image: &InMemoryImage,
+    channels: &TexChannels,
+    bit_depth: &TexBitDepth,
+) -> Result<RawLdrImage> {
+    decode_and_convert_ldr_image(&image.encoded, *channels, *bit_depth)
+}
+
+#[snoozy]
+pub async fn load_tex_from_memory_snoozy(
+    mut ctx: Context,
+    image: &InMemoryImage,
+    params: &TexParams,
+) -> Result<Texture> {
+    let raw_img = ctx
+        .get(&load_raw_ldr_tex_from_memory(
+            image.clone(),
+            params.channels,
+            get_ldr_bit_depth(params),
+        ))
+        .await?;
+    load_ldr_tex(&*raw_img, params)
+}
+
 #[snoozy]
 pub async fn make_placeholder_rgba8_tex_snoozy(
     _ctx: Context,